@@ -10,4 +10,22 @@ fn main() {
         let git_hash = String::from_utf8(output.stdout).unwrap();
         println!("cargo:rustc-env=GIT_HASH={}", git_hash);
     }
+
+    // Keys the transpile cache, so output from another deno_ast is never reused
+    let lockfile = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let deno_ast_version = lockfile
+        .split("[[package]]")
+        .find(|package| package.contains("name = \"deno_ast\""))
+        .and_then(|package| {
+            package
+                .lines()
+                .find_map(|line| line.strip_prefix("version = "))
+                .map(|version| version.trim_matches('"').to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=DENO_AST_VERSION={}", deno_ast_version);
+    // Naming files stops cargo from rerunning this on every change, so the commit is watched too
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
 }
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::cell::Cell;
+
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer},
+        Frame,
+    },
+    desktop::{
+        space::{RenderElement, RenderZindex, SpaceOutputTuple},
+        Window,
+    },
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{Logical, Physical, Point, Rectangle, Scale},
+    wayland::output::Output,
+};
+
+use crate::{
+    shell::{
+        decorations::{close_button, frame_geometry, maximize_button, BORDER_WIDTH, TITLE_HEIGHT},
+        window::WindowExt,
+    },
+    state::{output::OutputExt, CommonState},
+};
+
+use super::CustomElem;
+
+const ACTIVE_COLOR: [f32; 4] = [0.2, 0.2, 0.24, 1.0];
+const INACTIVE_COLOR: [f32; 4] = [0.35, 0.35, 0.38, 1.0];
+const URGENT_COLOR: [f32; 4] = [0.85, 0.55, 0.15, 1.0];
+const CLOSE_COLOR: [f32; 4] = [0.8, 0.26, 0.24, 1.0];
+const MAXIMIZE_COLOR: [f32; 4] = [0.55, 0.55, 0.58, 1.0];
+
+/// What the decorations of a window looked like when last drawn, to know when to redraw them
+struct DrawnDecoration(Cell<Option<(Rectangle<i32, Logical>, [f32; 4])>>);
+
+/// A compositor-drawn title bar and border around a window
+pub struct DecorationElement {
+    id: usize,
+    /// The frame around the window, relative to the output
+    geometry: Rectangle<i32, Logical>,
+    color: [f32; 4],
+    damaged: bool,
+}
+
+impl DecorationElement {
+    fn new(window: &Window, geometry: Rectangle<i32, Logical>) -> Self {
+        let color = if window
+            .current_state()
+            .states
+            .contains(xdg_toplevel::State::Activated)
+        {
+            ACTIVE_COLOR
+        } else if window.urgent() {
+            URGENT_COLOR
+        } else {
+            INACTIVE_COLOR
+        };
+
+        let userdata = window.user_data();
+        userdata.insert_if_missing(|| DrawnDecoration(Cell::new(None)));
+        let drawn = userdata
+            .get::<DrawnDecoration>()
+            .unwrap()
+            .0
+            .replace(Some((geometry, color)));
+
+        DecorationElement {
+            id: window.id() as usize,
+            geometry,
+            color,
+            damaged: drawn != Some((geometry, color)),
+        }
+    }
+
+    /// Each part of the decorations relative to the frame, in drawing order
+    fn parts(&self) -> [(Rectangle<i32, Logical>, [f32; 4]); 6] {
+        let color = self.color;
+        let size = self.geometry.size;
+        [
+            // The title bar with the top border
+            (
+                Rectangle::from_loc_and_size((0, 0), (size.w, TITLE_HEIGHT + BORDER_WIDTH)),
+                color,
+            ),
+            (
+                Rectangle::from_loc_and_size((0, 0), (BORDER_WIDTH, size.h)),
+                color,
+            ),
+            (
+                Rectangle::from_loc_and_size((size.w - BORDER_WIDTH, 0), (BORDER_WIDTH, size.h)),
+                color,
+            ),
+            (
+                Rectangle::from_loc_and_size((0, size.h - BORDER_WIDTH), (size.w, BORDER_WIDTH)),
+                color,
+            ),
+            (maximize_button(size), MAXIMIZE_COLOR),
+            (close_button(size), CLOSE_COLOR),
+        ]
+    }
+}
+
+impl RenderElement<Gles2Renderer> for DecorationElement {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn location(&self, scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        self.geometry.loc.to_f64().to_physical(scale)
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        self.geometry.to_physical_precise_round(scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        scale: impl Into<Scale<f64>>,
+        _for_values: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        if self.damaged {
+            vec![Rectangle::from_loc_and_size((0, 0), self.geometry.size)
+                .to_physical_precise_up(scale)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        _log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let scale = scale.into();
+        let location = location.to_i32_round();
+        for (part, color) in self.parts() {
+            let part = part.to_physical_precise_round(scale);
+            let rects = damage
+                .iter()
+                .filter_map(|rect| rect.intersection(part))
+                .map(|mut rect| {
+                    rect.loc += location;
+                    rect
+                })
+                .collect::<Vec<_>>();
+            if !rects.is_empty() {
+                frame.clear(color, &rects)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Below every window, so windows stacked above cover them
+    fn z_index(&self) -> u8 {
+        RenderZindex::Shell as u8 - 1
+    }
+}
+
+/// Decorations for every server-side decorated window visible on the output
+pub fn decoration_custom_elements(state: &CommonState, output: &Output) -> Vec<CustomElem> {
+    let workspace = state.shell.active_workspace();
+    let output_geometry = output.geometry();
+
+    workspace
+        .space
+        .windows()
+        .filter(|window| window.server_decorated())
+        .filter(|window| !workspace.fullscreen.values().any(|w| w == *window))
+        .filter_map(|window| {
+            let mut geometry = window.geometry();
+            geometry.loc = workspace.space.window_location(window)?;
+            let mut frame = frame_geometry(geometry);
+            if !frame.overlaps(output_geometry) {
+                return None;
+            }
+            frame.loc -= output_geometry.loc;
+            Some(DecorationElement::new(window, frame).into())
+        })
+        .collect()
+}
@@ -1,23 +1,38 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use smithay::{
-    backend::renderer::{gles2::Gles2Renderer, Frame, ImportAll, Renderer},
+    backend::renderer::{
+        gles2::Gles2Renderer, utils::draw_surface_tree, Frame, ImportAll, Renderer,
+    },
     desktop::{
         draw_layer_popups, draw_layer_surface, draw_window, draw_window_popups,
         layer_map_for_output,
         space::{RenderElement, RenderError, SurfaceTree},
         utils::damage_from_surface_tree,
-        Window,
+        PopupManager, Window,
     },
-    utils::{Physical, Rectangle, Transform},
+    utils::{Physical, Point, Rectangle, Size, Transform},
     wayland::{output::Output, shell::wlr_layer::Layer as WlrLayer},
 };
 
 use crate::state::CommonState;
 
+mod decorations;
+mod notice;
+mod opacity;
+
+use self::decorations::{decoration_custom_elements, DecorationElement};
+use self::notice::{notice_custom_elements, NoticeElement};
+use self::opacity::{
+    restore_translucent_windows, take_translucent_windows, TranslucentWindowElement,
+};
+
 smithay::custom_elements! {
     pub CustomElem<=Gles2Renderer>;
     SurfaceTree=SurfaceTree,
+    Decoration=DecorationElement,
+    Notice=NoticeElement,
+    TranslucentWindow=TranslucentWindowElement,
 }
 
 pub trait AsGles2Renderer {
@@ -30,6 +45,8 @@ impl AsGles2Renderer for Gles2Renderer {
 }
 
 static CLEAR_COLOR: [f32; 4] = [0.153, 1.0, 0.165, 1.0];
+/// Shown while the session is locked, wherever the locker has not drawn anything
+static LOCK_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 
 pub fn cursor_custom_elements<R>(
     _renderer: &mut R,
@@ -49,7 +66,7 @@ pub fn needs_buffer_reset(output: &Output, state: &CommonState) -> bool {
 
     let will_render_custom = {
         let workspace = state.shell.active_workspace();
-        workspace.get_fullscreen(output).is_some()
+        workspace.get_fullscreen(output).is_some() || state.session_lock_state.is_locked()
     };
 
     let userdata = output.user_data();
@@ -74,6 +91,10 @@ where
     <R as Renderer>::TextureId: Clone + 'static,
     CustomElem: RenderElement<R>,
 {
+    if state.session_lock_state.is_locked() {
+        return render_session_lock(renderer, state, output);
+    }
+
     let workspace = state.shell.active_workspace();
     let is_fullscreen = workspace.get_fullscreen(output).cloned();
 
@@ -96,7 +117,8 @@ where
     <R as Renderer>::TextureId: Clone + 'static,
     CustomElem: RenderElement<R>,
 {
-    let mut custom_elements = Vec::<CustomElem>::new();
+    let mut custom_elements = decoration_custom_elements(state, output);
+    custom_elements.extend(notice_custom_elements(state, output));
 
     custom_elements.extend(cursor_custom_elements(
         renderer,
@@ -105,13 +127,77 @@ where
         hardware_cursor,
     ));
 
-    state.shell.active_workspace_mut().space.render_output(
+    let scale = output.current_scale().fractional_scale();
+    let size = output.current_mode().unwrap().size;
+    let space = &mut state.shell.active_workspace_mut().space;
+    let stacking = space.windows().cloned().collect::<Vec<_>>();
+    let (translucent, taken) = take_translucent_windows(space, output);
+    custom_elements.extend(translucent);
+    let result = space.render_output(
         renderer,
         &output,
         age as usize,
         CLEAR_COLOR,
         &custom_elements,
-    )
+    );
+    restore_translucent_windows(space, taken, &stacking);
+    result.map(|damage| damage.map(|damage| scaled_damage(damage, scale, size)))
+}
+
+/// At fractional scales surfaces are filtered into the pixels around them, so their damage is
+/// grown by a pixel. Damage is kept within the output either way.
+fn scaled_damage(
+    damage: Vec<Rectangle<i32, Physical>>,
+    scale: f64,
+    size: Size<i32, Physical>,
+) -> Vec<Rectangle<i32, Physical>> {
+    let output = Rectangle::from_loc_and_size((0, 0), size);
+    damage
+        .into_iter()
+        .filter_map(|mut rect| {
+            if scale.fract() != 0.0 {
+                rect.loc -= Point::from((1, 1));
+                rect.size += Size::from((2, 2));
+            }
+            rect.intersection(output)
+        })
+        .collect()
+}
+
+/// Renders the lock surface of the output over a solid color, and nothing else
+fn render_session_lock<R>(
+    renderer: &mut R,
+    state: &CommonState,
+    output: &Output,
+) -> Result<Option<Vec<Rectangle<i32, Physical>>>, RenderError<R>>
+where
+    R: Renderer + ImportAll,
+    <R as Renderer>::TextureId: Clone + 'static,
+{
+    let transform = Transform::from(output.current_transform());
+    let mode = output.current_mode().unwrap();
+    let scale = output.current_scale().fractional_scale();
+    let lock_surface = state.session_lock_state.lock_surface(output);
+
+    renderer
+        .render(mode.size, transform, |renderer, frame| {
+            let damage = [Rectangle::from_loc_and_size((0, 0), mode.size)];
+            frame.clear(LOCK_COLOR, &damage)?;
+            if let Some(surface) = lock_surface {
+                draw_surface_tree(
+                    renderer,
+                    frame,
+                    surface,
+                    scale,
+                    (0.0, 0.0),
+                    &damage,
+                    &slog_scope::logger(),
+                )?;
+            }
+            Ok(Some(damage.to_vec()))
+        })
+        .and_then(std::convert::identity)
+        .map_err(RenderError::<R>::Rendering)
 }
 
 /// Renders a Wayland window
@@ -131,7 +217,7 @@ where
     let mode = output.current_mode().unwrap();
     let scale = output.current_scale().fractional_scale();
 
-    let mut custom_elements = Vec::<CustomElem>::new();
+    let mut custom_elements = notice_custom_elements(state, output);
 
     custom_elements.extend(cursor_custom_elements(
         renderer,
@@ -143,6 +229,21 @@ where
     renderer
         .render(mode.size, transform, |renderer, frame| {
             let mut damage = window.accumulated_damage((0.0, 0.0), scale, None);
+            damage.extend(
+                PopupManager::popups_for_surface(window.toplevel().wl_surface())
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|(popup, location)| {
+                        damage_from_surface_tree(
+                            popup.wl_surface(),
+                            (window.geometry().loc + location - popup.geometry().loc)
+                                .to_f64()
+                                .to_physical(scale),
+                            scale,
+                            None,
+                        )
+                    }),
+            );
             frame.clear(
                 CLEAR_COLOR,
                 &[Rectangle::from_loc_and_size((0, 0), mode.size)],
@@ -176,7 +277,7 @@ where
                     geo.loc.to_f64().to_physical(scale),
                     &[Rectangle::from_loc_and_size(
                         (0, 0),
-                        geo.size.to_physical_precise_round(scale),
+                        geo.size.to_physical_precise_up(scale),
                     )],
                     &slog_scope::logger(),
                 )?;
@@ -188,7 +289,7 @@ where
                     geo.loc.to_f64().to_physical(scale),
                     &[Rectangle::from_loc_and_size(
                         (0, 0),
-                        geo.size.to_physical_precise_round(scale),
+                        geo.size.to_physical_precise_up(scale),
                     )],
                     &slog_scope::logger(),
                 )?;
@@ -216,7 +317,7 @@ where
                     rect
                 }))
             }
-            Ok(Some(damage))
+            Ok(Some(scaled_damage(damage, scale, mode.size)))
         })
         .and_then(std::convert::identity)
         .map_err(RenderError::<R>::Rendering)
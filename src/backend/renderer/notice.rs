@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{cell::Cell, time::Duration};
+
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer},
+        Frame,
+    },
+    desktop::space::{RenderElement, RenderZindex, SpaceOutputTuple},
+    utils::{Logical, Physical, Point, Rectangle, Scale},
+    wayland::output::Output,
+};
+
+use crate::state::{output::OutputExt, CommonState};
+
+use super::CustomElem;
+
+/// How long a script error stays pointed out
+const NOTICE_DURATION: Duration = Duration::from_secs(10);
+const NOTICE_HEIGHT: i32 = 6;
+const NOTICE_COLOR: [f32; 4] = [0.8, 0.26, 0.24, 1.0];
+/// Decorations use the window ids, this stays clear of them
+const NOTICE_ID: usize = usize::MAX;
+
+/// Whether the notice was drawn on an output in the last frame
+struct DrawnNotice(Cell<bool>);
+
+/// A bar along the top of an output pointing out a script error, for when there is no
+/// notification daemon to show it. The error itself is in the log.
+pub struct NoticeElement {
+    /// The bar, relative to the output
+    geometry: Rectangle<i32, Logical>,
+    damaged: bool,
+}
+
+impl RenderElement<Gles2Renderer> for NoticeElement {
+    fn id(&self) -> usize {
+        NOTICE_ID
+    }
+
+    fn location(&self, scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        self.geometry.loc.to_f64().to_physical(scale)
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        self.geometry.to_physical_precise_round(scale)
+    }
+
+    fn accumulated_damage(
+        &self,
+        scale: impl Into<Scale<f64>>,
+        _for_values: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        if self.damaged {
+            vec![Rectangle::from_loc_and_size((0, 0), self.geometry.size)
+                .to_physical_precise_up(scale)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        _log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let bar = Rectangle::from_loc_and_size((0, 0), self.geometry.size)
+            .to_physical_precise_round(scale);
+        let location = location.to_i32_round();
+        let rects = damage
+            .iter()
+            .filter_map(|rect| rect.intersection(bar))
+            .map(|mut rect| {
+                rect.loc += location;
+                rect
+            })
+            .collect::<Vec<_>>();
+        if !rects.is_empty() {
+            frame.clear(NOTICE_COLOR, &rects)?;
+        }
+        Ok(())
+    }
+
+    /// Above everything, layer shell overlays included
+    fn z_index(&self) -> u8 {
+        RenderZindex::Overlay as u8 + 1
+    }
+}
+
+/// The notice for the output, while a script error is recent
+pub fn notice_custom_elements(state: &CommonState, output: &Output) -> Vec<CustomElem> {
+    let visible = state
+        .script_error
+        .map(|since| since.elapsed() < NOTICE_DURATION)
+        .unwrap_or(false);
+
+    let userdata = output.user_data();
+    userdata.insert_if_missing(|| DrawnNotice(Cell::new(false)));
+    let drawn = userdata.get::<DrawnNotice>().unwrap().0.replace(visible);
+    if !visible {
+        return Vec::new();
+    }
+
+    let width = output.geometry().size.w;
+    vec![NoticeElement {
+        geometry: Rectangle::from_loc_and_size((0, 0), (width, NOTICE_HEIGHT)),
+        damaged: !drawn,
+    }
+    .into()]
+}
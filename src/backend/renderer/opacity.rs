@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    backend::renderer::{
+        gles2::{Gles2Error, Gles2Frame, Gles2Renderer},
+        utils::{import_surface_tree, RendererSurfaceStateUserData},
+        Frame, Renderer,
+    },
+    desktop::{
+        draw_window_popups,
+        space::{RenderElement, RenderZindex, SpaceOutputTuple},
+        Space, Window,
+    },
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Physical, Point, Rectangle, Scale},
+    wayland::{
+        compositor::{with_surface_tree_upward, SubsurfaceCachedState, TraversalAction},
+        output::Output,
+    },
+};
+
+use crate::{
+    shell::{rules::WindowProperties, window::WindowExt},
+    state::output::OutputExt,
+};
+
+use super::CustomElem;
+
+/// Decorations use the window ids, this keeps the ids of translucent windows apart from them
+const ID_BIT: usize = 1 << (usize::BITS - 1);
+
+/// A window given an opacity below 1 by a rule. The space only draws windows opaque, so these
+/// are drawn as custom elements instead, while the space renders without them.
+pub struct TranslucentWindowElement {
+    window: Window,
+    /// Where the window is in the space
+    location: Point<i32, Logical>,
+    /// Where the window's surface is, relative to the output
+    surface_location: Point<i32, Logical>,
+    alpha: f32,
+}
+
+impl TranslucentWindowElement {
+    /// Everything the window draws, relative to the output
+    fn bbox(&self) -> Rectangle<i32, Logical> {
+        let mut bbox = self.window.bbox_with_popups();
+        bbox.loc += self.surface_location;
+        bbox
+    }
+}
+
+impl RenderElement<Gles2Renderer> for TranslucentWindowElement {
+    fn id(&self) -> usize {
+        ID_BIT | self.window.id() as usize
+    }
+
+    fn location(&self, scale: impl Into<Scale<f64>>) -> Point<f64, Physical> {
+        self.surface_location.to_f64().to_physical(scale)
+    }
+
+    fn geometry(&self, scale: impl Into<Scale<f64>>) -> Rectangle<i32, Physical> {
+        self.bbox().to_physical_precise_round(scale)
+    }
+
+    /// The window is not damage tracked on its own, so it is redrawn with every frame
+    fn accumulated_damage(
+        &self,
+        scale: impl Into<Scale<f64>>,
+        _for_values: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Physical>> {
+        vec![self.window.bbox_with_popups().to_physical_precise_up(scale)]
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut Gles2Renderer,
+        frame: &mut Gles2Frame,
+        scale: impl Into<Scale<f64>>,
+        location: Point<f64, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        log: &slog::Logger,
+    ) -> Result<(), Gles2Error> {
+        let scale = scale.into();
+        let surface = self.window.toplevel().wl_surface();
+        import_surface_tree(renderer, surface, log)?;
+        draw_surface_tree_with_alpha(
+            renderer, frame, surface, scale, location, damage, self.alpha,
+        )?;
+        draw_window_popups(renderer, frame, &self.window, scale, location, damage, log)
+    }
+
+    /// Stacked with the windows, above their decorations
+    fn z_index(&self) -> u8 {
+        RenderZindex::Shell as u8
+    }
+}
+
+/// Draws a surface and its subsurfaces like smithay's `draw_surface_tree`, with the given alpha
+fn draw_surface_tree_with_alpha(
+    renderer: &mut Gles2Renderer,
+    frame: &mut Gles2Frame,
+    surface: &WlSurface,
+    scale: Scale<f64>,
+    location: Point<f64, Physical>,
+    damage: &[Rectangle<i32, Physical>],
+    alpha: f32,
+) -> Result<(), Gles2Error> {
+    let renderer_id = renderer.id();
+    let mut result = Ok(());
+    with_surface_tree_upward(
+        surface,
+        location,
+        |_surface, states, location| {
+            let mut location = *location;
+            if states.role == Some("subsurface") {
+                let current = states.cached_state.current::<SubsurfaceCachedState>();
+                location += current.location.to_f64().to_physical(scale);
+            }
+            TraversalAction::DoChildren(location)
+        },
+        |_surface, states, location| {
+            if result.is_err() {
+                return;
+            }
+            let mut location = *location;
+            if states.role == Some("subsurface") {
+                let current = states.cached_state.current::<SubsurfaceCachedState>();
+                location += current.location.to_f64().to_physical(scale);
+            }
+
+            let data = match states.data_map.get::<RendererSurfaceStateUserData>() {
+                Some(data) => data.borrow(),
+                None => return,
+            };
+            let (view, texture) = match (data.view(), data.texture::<Gles2Renderer>(renderer_id)) {
+                (Some(view), Some(texture)) => (view, texture),
+                _ => return,
+            };
+            let buffer_size = match data.buffer_size() {
+                Some(size) => size,
+                None => return,
+            };
+
+            let dst = Rectangle::from_loc_and_size(
+                location + view.offset.to_f64().to_physical(scale),
+                view.dst.to_f64().to_physical(scale),
+            );
+            // Damage is relative to the element, the frame wants it relative to the surface
+            let offset = dst.loc - location;
+            let damage = damage
+                .iter()
+                .map(|rect| rect.to_f64())
+                .filter_map(|rect| {
+                    rect.intersection(Rectangle::from_loc_and_size(offset, dst.size))
+                })
+                .map(|mut rect| {
+                    rect.loc -= offset;
+                    rect.to_i32_up()
+                })
+                .collect::<Vec<_>>();
+            if damage.is_empty() {
+                return;
+            }
+
+            let src = view.src.to_buffer(
+                data.buffer_scale() as f64,
+                data.buffer_transform(),
+                &buffer_size
+                    .to_logical(data.buffer_scale(), data.buffer_transform())
+                    .to_f64(),
+            );
+            result = frame.render_texture_from_to(
+                texture,
+                src,
+                dst,
+                &damage,
+                data.buffer_transform(),
+                alpha,
+            );
+        },
+        |_, _, _| true,
+    );
+    result
+}
+
+/// The translucent windows on the output, bottom to top
+fn translucent_windows(space: &Space, output: &Output) -> Vec<TranslucentWindowElement> {
+    let output_geometry = output.geometry();
+    space
+        .windows()
+        .filter(|window| space.outputs_for_window(window).contains(output))
+        .filter_map(|window| {
+            let alpha = WindowProperties::get(window).opacity?.clamp(0.0, 1.0);
+            if alpha >= 1.0 {
+                return None;
+            }
+            let location = space.window_location(window)?;
+            Some(TranslucentWindowElement {
+                window: window.clone(),
+                location,
+                surface_location: location - window.geometry().loc - output_geometry.loc,
+                alpha,
+            })
+        })
+        .collect()
+}
+
+/// Takes the translucent windows on the output out of the space, returning them as custom
+/// elements. They go back with `restore_translucent_windows` once the space has rendered.
+pub fn take_translucent_windows(
+    space: &mut Space,
+    output: &Output,
+) -> (Vec<CustomElem>, Vec<(Window, Point<i32, Logical>)>) {
+    let elements = translucent_windows(space, output);
+    let taken = elements
+        .iter()
+        .map(|element| {
+            space.unmap_window(&element.window);
+            (element.window.clone(), element.location)
+        })
+        .collect();
+    (elements.into_iter().map(CustomElem::from).collect(), taken)
+}
+
+/// Maps the windows `take_translucent_windows` took back where they were, keeping the stacking
+/// order the space had
+pub fn restore_translucent_windows(
+    space: &mut Space,
+    taken: Vec<(Window, Point<i32, Logical>)>,
+    stacking: &[Window],
+) {
+    if taken.is_empty() {
+        return;
+    }
+    for (window, location) in taken {
+        space.map_window(&window, location, None, false);
+    }
+    for window in stacking {
+        space.raise_window(window, false);
+    }
+}
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::error::Error;
+use std::{env, error::Error};
 
 use crate::{
     input::Devices,
@@ -57,7 +57,7 @@ pub fn init_backend(
     output.change_current_state(
         Some(mode),
         Some(Transform::Flipped180),
-        Some(Scale::Integer(1)),
+        Some(initial_scale()),
         Some((0, 0).into()),
     );
     // TODO Insert User Data
@@ -72,11 +72,12 @@ pub fn init_backend(
         event_loop
             .handle()
             .insert_source(render_source, move |_, _, data| {
+                let dh = data.display.handle();
                 if let Err(err) = data
                     .state
                     .backend
                     .winit()
-                    .render_output(&mut data.state.common)
+                    .render_output(&dh, &mut data.state.common)
                 {
                     slog_scope::error!("Failed to render frame: {}", err);
                     render_ping.ping();
@@ -120,6 +121,27 @@ pub fn init_backend(
     Ok(())
 }
 
+/// The scale of the window from `ELECTRUM_SCALE`, until the script sets another
+fn initial_scale() -> Scale {
+    let scale = match env::var("ELECTRUM_SCALE") {
+        Ok(scale) => scale,
+        Err(_) => return Scale::Integer(1),
+    };
+    match scale.parse::<f64>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => {
+            if scale.fract() == 0.0 {
+                Scale::Integer(scale as i32)
+            } else {
+                Scale::Fractional(scale)
+            }
+        }
+        _ => {
+            slog_scope::warn!("Ignoring invalid ELECTRUM_SCALE {:?}", scale);
+            Scale::Integer(1)
+        }
+    }
+}
+
 fn init_egl(
     dh: &DisplayHandle,
     state: &mut State,
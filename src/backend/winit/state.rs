@@ -3,9 +3,20 @@
 use std::error::Error;
 
 use anyhow::Context;
-use smithay::{backend::winit::WinitGraphicsBackend, wayland::output::Output};
+use smithay::{
+    backend::winit::WinitGraphicsBackend,
+    reexports::{
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::DisplayHandle,
+    },
+    wayland::output::Output,
+};
 
-use crate::{backend::renderer, state::CommonState};
+use crate::{
+    backend::renderer,
+    runtime::messages::RuntimeMessage,
+    state::{output::OutputExt, CommonState},
+};
 
 pub struct WinitState {
     pub backend: WinitGraphicsBackend,
@@ -14,7 +25,11 @@ pub struct WinitState {
 }
 
 impl WinitState {
-    pub fn render_output(&mut self, state: &mut CommonState) -> Result<(), Box<dyn Error>> {
+    pub fn render_output(
+        &mut self,
+        dh: &DisplayHandle,
+        state: &mut CommonState,
+    ) -> Result<(), Box<dyn Error>> {
         if renderer::needs_buffer_reset(&self.output, state) {
             self.reset_buffers();
         }
@@ -38,14 +53,32 @@ impl WinitState {
             true,
         ) {
             Ok(damage) => {
-                state
-                    .shell
-                    .active_workspace_mut()
-                    .space
-                    .send_frames(state.start_time.elapsed().as_millis() as u32);
+                let time = state.start_time.elapsed().as_millis() as u32;
+                let locked = state.session_lock_state.is_locked();
+                if !locked {
+                    state.shell.active_workspace_mut().space.send_frames(time);
+                }
                 self.backend
                     .submit(damage.as_ref().map(|x| &**x))
                     .with_context(|| "Failed to submit buffer for display")?;
+                // Winit blocks on the swap, so the frame is on screen by now
+                if locked {
+                    state.session_lock_state.frame_presented(&self.output, time);
+                } else {
+                    state.shell.send_presentation_feedback(
+                        dh,
+                        &self.output,
+                        wp_presentation_feedback::Kind::Vsync,
+                    );
+                }
+                if self.output.take_frame_request() {
+                    let _ = state.shell.active_workspace().runtime_sender.send(
+                        RuntimeMessage::OutputFrame {
+                            output: self.output.clone(),
+                            time,
+                        },
+                    );
+                }
             }
             Err(err) => {
                 // TODO handle errors better
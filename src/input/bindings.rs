@@ -0,0 +1,503 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use deno_core::serde::{Deserialize, Serialize};
+use smithay::{reexports::calloop::RegistrationToken, wayland::seat::ModifiersState};
+use xkbcommon::xkb;
+
+pub const DEFAULT_MODE: &str = "default";
+/// Delay before a held key repeats in milliseconds, shared by clients and repeating bindings
+pub const REPEAT_DELAY_MS: i32 = 200;
+/// Repeats per second of a held key
+pub const REPEAT_RATE: i32 = 25;
+pub const REPEAT_DELAY: Duration = Duration::from_millis(REPEAT_DELAY_MS as u64);
+pub const REPEAT_INTERVAL: Duration = Duration::from_millis(1000 / REPEAT_RATE as u64);
+
+// linux/input-event-codes.h
+pub const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+}
+
+impl Modifiers {
+    pub fn matches(&self, state: &ModifiersState) -> bool {
+        self.ctrl == state.ctrl
+            && self.alt == state.alt
+            && self.shift == state.shift
+            && self.logo == state.logo
+    }
+
+    /// Sets the modifier with the given name, returning false if there is no such modifier
+    fn set(&mut self, name: &str) -> bool {
+        match name.to_lowercase().as_str() {
+            "ctrl" | "control" => self.ctrl = true,
+            "alt" | "mod1" => self.alt = true,
+            "shift" => self.shift = true,
+            "super" | "logo" | "mod4" => self.logo = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl FromStr for Modifiers {
+    type Err = anyhow::Error;
+
+    /// Parses modifiers on their own, such as `Super+Shift`
+    fn from_str(names: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        for name in names.split('+').map(str::trim).filter(|n| !n.is_empty()) {
+            if !modifiers.set(name) {
+                bail!("Unknown modifier {:?}", name);
+            }
+        }
+        Ok(modifiers)
+    }
+}
+
+/// Splits a combination such as `Super+Shift+Return` into its modifiers and final part
+fn parse_combo(combo: &str) -> Result<(Modifiers, &str), anyhow::Error> {
+    let (modifiers, last) = match combo.rsplit_once('+') {
+        Some((modifiers, last)) => (modifiers.parse()?, last.trim()),
+        None => (Modifiers::default(), combo.trim()),
+    };
+    if last.is_empty() {
+        bail!("Binding {:?} is incomplete", combo);
+    }
+
+    Ok((modifiers, last))
+}
+
+/// A key combination such as `Super+Shift+Return`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub modifiers: Modifiers,
+    pub keysym: u32,
+}
+
+impl KeyCombo {
+    /// Whether the held modifiers and any of the unmodified keysyms of a pressed key match
+    pub fn matches(&self, modifiers: &ModifiersState, keysyms: &[u32]) -> bool {
+        self.modifiers.matches(modifiers) && keysyms.contains(&self.keysym)
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = anyhow::Error;
+
+    fn from_str(combo: &str) -> Result<Self, Self::Err> {
+        let (modifiers, key) = parse_combo(combo)?;
+
+        let keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+        if keysym == xkb::keysyms::KEY_NoSymbol {
+            bail!("Unknown key {:?} in key binding {:?}", key, combo);
+        }
+
+        Ok(KeyCombo { modifiers, keysym })
+    }
+}
+
+/// A pointer button combination such as `Super+Left`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonCombo {
+    pub modifiers: Modifiers,
+    pub button: u32,
+}
+
+impl FromStr for ButtonCombo {
+    type Err = anyhow::Error;
+
+    fn from_str(combo: &str) -> Result<Self, Self::Err> {
+        let (modifiers, button) = parse_combo(combo)?;
+
+        let button = match button.to_lowercase().as_str() {
+            "left" => BTN_LEFT,
+            "right" => BTN_RIGHT,
+            "middle" => BTN_MIDDLE,
+            "side" | "back" => BTN_SIDE,
+            "extra" | "forward" => BTN_EXTRA,
+            code => code
+                .parse()
+                .map_err(|_| anyhow!("Unknown button {:?} in binding {:?}", button, combo))?,
+        };
+
+        Ok(ButtonCombo { modifiers, button })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// A scroll combination such as `Super+ScrollUp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxisCombo {
+    pub modifiers: Modifiers,
+    pub direction: AxisDirection,
+}
+
+impl FromStr for AxisCombo {
+    type Err = anyhow::Error;
+
+    fn from_str(combo: &str) -> Result<Self, Self::Err> {
+        let (modifiers, direction) = parse_combo(combo)?;
+
+        let direction = match direction.to_lowercase().as_str() {
+            "scrollup" => AxisDirection::Up,
+            "scrolldown" => AxisDirection::Down,
+            "scrollleft" => AxisDirection::Left,
+            "scrollright" => AxisDirection::Right,
+            _ => bail!(
+                "Unknown scroll direction {:?} in binding {:?}",
+                direction,
+                combo
+            ),
+        };
+
+        Ok(AxisCombo {
+            modifiers,
+            direction,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum GestureKind {
+    Swipe,
+    Pinch,
+}
+
+/// What the compositor does itself when a button binding is pressed
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ButtonAction {
+    /// Moves the window under the pointer until the button is released
+    Move,
+    /// Resizes the window under the pointer from the nearest corner until the button is released
+    Resize,
+    /// Only tells the script
+    Event,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyBinding {
+    pub id: u32,
+    pub combo: KeyCombo,
+    pub mode: String,
+    pub on_press: bool,
+    pub on_release: bool,
+    pub repeat: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ButtonBinding {
+    pub id: u32,
+    pub combo: ButtonCombo,
+    pub mode: String,
+    pub action: ButtonAction,
+}
+
+#[derive(Debug, Clone)]
+pub struct AxisBinding {
+    pub id: u32,
+    pub combo: AxisCombo,
+    pub mode: String,
+    /// Only triggers when there is no window under the pointer
+    pub root: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct GestureBinding {
+    pub id: u32,
+    pub kind: GestureKind,
+    pub fingers: u32,
+    pub modifiers: Modifiers,
+    pub mode: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Binding {
+    Key(KeyBinding),
+    Button(ButtonBinding),
+    Axis(AxisBinding),
+    Gesture(GestureBinding),
+}
+
+impl Binding {
+    fn id(&self) -> u32 {
+        match self {
+            Binding::Key(binding) => binding.id,
+            Binding::Button(binding) => binding.id,
+            Binding::Axis(binding) => binding.id,
+            Binding::Gesture(binding) => binding.id,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingState {
+    Pressed,
+    Released,
+    Repeated,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum GestureUpdate {
+    Begin,
+    Update {
+        dx: f64,
+        dy: f64,
+        scale: f64,
+        rotation: f64,
+    },
+    End {
+        cancelled: bool,
+    },
+}
+
+/// Bindings claimed by the script, grouped into modes of which one is active at a time
+pub struct Bindings {
+    mode: String,
+    keys: Vec<KeyBinding>,
+    buttons: Vec<ButtonBinding>,
+    axes: Vec<AxisBinding>,
+    gestures: Vec<GestureBinding>,
+    pub repeat: Option<(u32, RegistrationToken)>,
+    /// The gesture binding claiming the gesture in progress
+    pub gesture: Option<u32>,
+    /// Smooth scrolling collected towards the next axis binding step, per axis
+    pub scroll: [f64; 2],
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            mode: DEFAULT_MODE.to_string(),
+            keys: Vec::new(),
+            buttons: Vec::new(),
+            axes: Vec::new(),
+            gestures: Vec::new(),
+            repeat: None,
+            gesture: None,
+            scroll: [0.0; 2],
+        }
+    }
+}
+
+impl Bindings {
+    pub fn add(&mut self, binding: Binding) {
+        self.remove(binding.id());
+        match binding {
+            Binding::Key(binding) => self.keys.push(binding),
+            Binding::Button(binding) => self.buttons.push(binding),
+            Binding::Axis(binding) => self.axes.push(binding),
+            Binding::Gesture(binding) => self.gestures.push(binding),
+        }
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.keys.retain(|b| b.id != id);
+        self.buttons.retain(|b| b.id != id);
+        self.axes.retain(|b| b.id != id);
+        self.gestures.retain(|b| b.id != id);
+    }
+
+    pub fn key(&self, id: u32) -> Option<&KeyBinding> {
+        self.keys.iter().find(|b| b.id == id)
+    }
+
+    pub fn set_mode(&mut self, mode: String) {
+        self.mode = mode;
+    }
+
+    /// Drops every binding, for when the script that registered them goes away
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.buttons.clear();
+        self.axes.clear();
+        self.gestures.clear();
+        self.mode = DEFAULT_MODE.to_string();
+        self.gesture = None;
+        self.scroll = [0.0; 2];
+    }
+
+    /// Finds the key binding in the active mode matching the held modifiers and any of the
+    /// unmodified keysyms of the pressed key
+    pub fn find_key(&self, modifiers: &ModifiersState, keysyms: &[u32]) -> Option<&KeyBinding> {
+        self.keys
+            .iter()
+            .find(|binding| binding.mode == self.mode && binding.combo.matches(modifiers, keysyms))
+    }
+
+    pub fn find_button(&self, modifiers: &ModifiersState, button: u32) -> Option<&ButtonBinding> {
+        self.buttons.iter().find(|binding| {
+            binding.mode == self.mode
+                && binding.combo.modifiers.matches(modifiers)
+                && binding.combo.button == button
+        })
+    }
+
+    pub fn find_axis(
+        &self,
+        modifiers: &ModifiersState,
+        direction: AxisDirection,
+        on_root: bool,
+    ) -> Option<&AxisBinding> {
+        self.axes.iter().find(|binding| {
+            binding.mode == self.mode
+                && binding.combo.modifiers.matches(modifiers)
+                && binding.combo.direction == direction
+                && (on_root || !binding.root)
+        })
+    }
+
+    pub fn find_gesture(
+        &self,
+        modifiers: &ModifiersState,
+        kind: GestureKind,
+        fingers: u32,
+    ) -> Option<&GestureBinding> {
+        self.gestures.iter().find(|binding| {
+            binding.mode == self.mode
+                && binding.modifiers.matches(modifiers)
+                && binding.kind == kind
+                && binding.fingers == fingers
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modifiers(logo: bool, shift: bool) -> ModifiersState {
+        ModifiersState {
+            logo,
+            shift,
+            ..Default::default()
+        }
+    }
+
+    fn key_binding(id: u32, combo: &str, mode: &str) -> Binding {
+        Binding::Key(KeyBinding {
+            id,
+            combo: combo.parse().unwrap(),
+            mode: mode.to_string(),
+            on_press: true,
+            on_release: false,
+            repeat: false,
+        })
+    }
+
+    #[test]
+    fn parses_valid_combos() {
+        let combo: KeyCombo = "Super+Shift+Return".parse().unwrap();
+        assert_eq!(
+            combo.modifiers,
+            Modifiers {
+                logo: true,
+                shift: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(combo.keysym, xkb::keysyms::KEY_Return);
+
+        let combo: KeyCombo = " ctrl + alt + a ".parse().unwrap();
+        assert!(combo.modifiers.ctrl && combo.modifiers.alt);
+        assert_eq!(combo.keysym, xkb::keysyms::KEY_a);
+
+        let combo: KeyCombo = "Escape".parse().unwrap();
+        assert_eq!(combo.modifiers, Modifiers::default());
+
+        let combo: ButtonCombo = "Mod4+Left".parse().unwrap();
+        assert!(combo.modifiers.logo);
+        assert_eq!(combo.button, BTN_LEFT);
+        let combo: ButtonCombo = "Super+274".parse().unwrap();
+        assert_eq!(combo.button, BTN_MIDDLE);
+
+        let combo: AxisCombo = "Alt+ScrollDown".parse().unwrap();
+        assert!(combo.modifiers.alt);
+        assert_eq!(combo.direction, AxisDirection::Down);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        assert!("Super+NotAKey".parse::<KeyCombo>().is_err());
+        assert!("Hyper+Return".parse::<KeyCombo>().is_err());
+        assert!("Super+Thumb".parse::<ButtonCombo>().is_err());
+        assert!("Super+ScrollSideways".parse::<AxisCombo>().is_err());
+        assert!("Super+Shift+Meta".parse::<Modifiers>().is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_combos() {
+        assert!("Super+".parse::<KeyCombo>().is_err());
+        assert!("".parse::<KeyCombo>().is_err());
+        assert!("Super+ ".parse::<ButtonCombo>().is_err());
+    }
+
+    #[test]
+    fn finds_bindings_of_the_active_mode() {
+        let mut bindings = Bindings::default();
+        bindings.add(key_binding(1, "Super+Return", DEFAULT_MODE));
+        bindings.add(key_binding(2, "Super+Return", "resize"));
+        bindings.add(Binding::Axis(AxisBinding {
+            id: 3,
+            combo: "Super+ScrollUp".parse().unwrap(),
+            mode: "resize".to_string(),
+            root: true,
+        }));
+        bindings.add(Binding::Gesture(GestureBinding {
+            id: 4,
+            kind: GestureKind::Swipe,
+            fingers: 3,
+            modifiers: Modifiers::default(),
+            mode: DEFAULT_MODE.to_string(),
+        }));
+        let keysyms = [xkb::keysyms::KEY_Return];
+
+        let found = bindings.find_key(&modifiers(true, false), &keysyms);
+        assert_eq!(found.map(|binding| binding.id), Some(1));
+        assert!(bindings
+            .find_key(&modifiers(true, true), &keysyms)
+            .is_none());
+        assert!(bindings
+            .find_axis(&modifiers(true, false), AxisDirection::Up, true)
+            .is_none());
+        let found = bindings.find_gesture(&modifiers(false, false), GestureKind::Swipe, 3);
+        assert_eq!(found.map(|binding| binding.id), Some(4));
+
+        bindings.set_mode("resize".to_string());
+        let found = bindings.find_key(&modifiers(true, false), &keysyms);
+        assert_eq!(found.map(|binding| binding.id), Some(2));
+        let found = bindings.find_axis(&modifiers(true, false), AxisDirection::Up, true);
+        assert_eq!(found.map(|binding| binding.id), Some(3));
+        assert!(bindings
+            .find_axis(&modifiers(true, false), AxisDirection::Up, false)
+            .is_none());
+        assert!(bindings
+            .find_gesture(&modifiers(false, false), GestureKind::Swipe, 3)
+            .is_none());
+
+        bindings.clear();
+        let found = bindings.find_key(&modifiers(true, false), &keysyms);
+        assert!(found.is_none());
+    }
+}
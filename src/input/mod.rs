@@ -1,16 +1,124 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use smithay::backend::input::{Device, DeviceCapability, InputBackend, InputEvent};
+use smithay::backend::input::{
+    AbsolutePositionEvent, Axis, AxisSource, ButtonState, Device, DeviceCapability, Event,
+    GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent, GestureSwipeUpdateEvent,
+    InputBackend, InputEvent, KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent,
+    PointerMotionEvent,
+};
+use smithay::reexports::calloop::timer::{TimeoutAction, Timer};
 
-use smithay::reexports::wayland_server::DisplayHandle;
-use smithay::wayland::seat::{CursorImageStatus, Seat};
+use smithay::reexports::{
+    wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge,
+    wayland_server::{backend::ClientId, protocol::wl_surface::WlSurface, DisplayHandle, Resource},
+};
+use smithay::utils::{Logical, Point};
+use smithay::wayland::seat::{
+    AxisFrame, ButtonEvent, CursorImageStatus, FilterResult, Focus, ModifiersState, MotionEvent,
+    PointerGrabStartData, Seat, XkbConfig,
+};
+use smithay::wayland::{Serial, SERIAL_COUNTER};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
-use crate::state::State;
+use crate::runtime::messages::RuntimeMessage;
+use crate::shell::grabs::{MoveSurfaceGrab, ResizeSurfaceGrab};
+use crate::state::{
+    output::{active_output, OutputExt},
+    State,
+};
 
+use self::bindings::{
+    AxisDirection, BindingState, ButtonAction, GestureKind, GestureUpdate, REPEAT_DELAY,
+    REPEAT_DELAY_MS, REPEAT_INTERVAL, REPEAT_RATE,
+};
+
+pub mod bindings;
+
+/// How many presses `RecentInput` remembers
+const RECENT_INPUT_LEN: usize = 16;
+/// How far smooth scrolling goes for each trigger of an axis binding
+const SCROLL_STEP: f64 = 15.0;
+
+/// Keys claimed by a binding, with the binding's id, so their release is not forwarded either.
+/// Keys claimed by the compositor itself have no id.
 #[derive(Default)]
-pub struct SupressedKeys(RefCell<Vec<u32>>);
+pub struct SupressedKeys(RefCell<Vec<(u32, Option<u32>)>>);
+
+impl SupressedKeys {
+    fn add(&self, keycode: u32, binding: Option<u32>) {
+        self.0.borrow_mut().push((keycode, binding));
+    }
+
+    fn remove(&self, keycode: u32) -> Option<Option<u32>> {
+        let mut keys = self.0.borrow_mut();
+        let pos = keys.iter().position(|(key, _)| *key == keycode)?;
+        Some(keys.remove(pos).1)
+    }
+}
+
+/// Buttons claimed by a binding, with the binding's id, so their release is not forwarded either
+#[derive(Default)]
+pub struct SupressedButtons(RefCell<Vec<(u32, u32)>>);
+
+impl SupressedButtons {
+    fn add(&self, button: u32, binding: u32) {
+        self.0.borrow_mut().push((button, binding));
+    }
+
+    fn remove(&self, button: u32) -> Option<u32> {
+        let mut buttons = self.0.borrow_mut();
+        let pos = buttons.iter().position(|(b, _)| *b == button)?;
+        Some(buttons.remove(pos).1)
+    }
+}
+
+/// Recent key and button presses on a seat with the client each went to, so activation tokens
+/// can be checked against them
+#[derive(Default)]
+pub struct RecentInput(RefCell<VecDeque<(Serial, ClientId)>>);
+
+impl RecentInput {
+    fn add(&self, serial: Serial, client: ClientId) {
+        let mut inputs = self.0.borrow_mut();
+        inputs.push_back((serial, client));
+        if inputs.len() > RECENT_INPUT_LEN {
+            inputs.pop_front();
+        }
+    }
+
+    /// Whether the press with this serial went to the client
+    pub fn went_to(&self, serial: Serial, client: &ClientId) -> bool {
+        self.0
+            .borrow()
+            .iter()
+            .any(|(s, c)| *s == serial && c == client)
+    }
+}
+
+/// The xkb configuration the seat keyboard was created with, so it can get its keymap back
+/// after a virtual keyboard typed into it
+#[derive(Debug, Default, Clone)]
+pub struct SeatXkbConfig {
+    pub rules: String,
+    pub model: String,
+    pub layout: String,
+    pub variant: String,
+    pub options: Option<String>,
+}
+
+impl SeatXkbConfig {
+    pub fn xkb_config(&self) -> XkbConfig<'_> {
+        XkbConfig {
+            rules: &self.rules,
+            model: &self.model,
+            layout: &self.layout,
+            variant: &self.variant,
+            options: self.options.clone(),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Devices(RefCell<HashMap<String, Vec<DeviceCapability>>>);
 
@@ -47,12 +155,79 @@ impl Devices {
     }
 }
 
+/// A scroll from a real or virtual pointer
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollEvent {
+    pub source: AxisSource,
+    pub time: u32,
+    /// Continuous amounts, horizontal then vertical
+    pub amount: [Option<f64>; 2],
+    /// Wheel steps, horizontal then vertical
+    pub discrete: [Option<f64>; 2],
+    /// Whether scrolling stopped, horizontal then vertical
+    pub stop: [bool; 2],
+}
+
+impl ScrollEvent {
+    pub fn new(source: AxisSource, time: u32) -> Self {
+        ScrollEvent {
+            source,
+            time,
+            amount: [None; 2],
+            discrete: [None; 2],
+            stop: [false; 2],
+        }
+    }
+
+    fn from_event<B: InputBackend>(event: &B::PointerAxisEvent) -> Self {
+        let mut scroll = ScrollEvent::new(event.source(), event.time());
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            scroll.amount[Self::index(axis)] = event.amount(axis);
+            scroll.discrete[Self::index(axis)] = event.amount_discrete(axis);
+            // libinput ends touchpad scrolls with a zero amount
+            scroll.stop[Self::index(axis)] =
+                event.source() == AxisSource::Finger && event.amount(axis) == Some(0.0);
+        }
+        scroll
+    }
+
+    pub fn index(axis: Axis) -> usize {
+        match axis {
+            Axis::Horizontal => 0,
+            Axis::Vertical => 1,
+        }
+    }
+
+    fn amount(&self, axis: Axis) -> Option<f64> {
+        self.amount[Self::index(axis)]
+    }
+
+    fn amount_discrete(&self, axis: Axis) -> Option<f64> {
+        self.discrete[Self::index(axis)]
+    }
+
+    fn stops(&self, axis: Axis) -> bool {
+        self.stop[Self::index(axis)]
+    }
+
+    /// Whether there is anything to scroll
+    pub fn is_empty(&self) -> bool {
+        self.amount
+            .iter()
+            .chain(&self.discrete)
+            .all(Option::is_none)
+            && !self.stop.contains(&true)
+    }
+}
+
 pub fn add_seat(dh: &DisplayHandle, name: String) -> Seat<State> {
     let mut seat = Seat::<State>::new(dh, name, None);
     let userdata = seat.user_data();
     // userdata.insert_if_missing(SeatId::default);
     userdata.insert_if_missing(Devices::default);
     userdata.insert_if_missing(SupressedKeys::default);
+    userdata.insert_if_missing(SupressedButtons::default);
+    userdata.insert_if_missing(RecentInput::default);
     userdata.insert_if_missing(|| RefCell::new(CursorImageStatus::Default));
 
     let owned_seat = seat.clone();
@@ -63,6 +238,10 @@ pub fn add_seat(dh: &DisplayHandle, name: String) -> Seat<State> {
             .unwrap()
             .borrow_mut() = status;
     });
+    let xkb_config = SeatXkbConfig::default();
+    seat.add_keyboard(xkb_config.xkb_config(), REPEAT_DELAY_MS, REPEAT_RATE)
+        .expect("Failed to initialise keyboard");
+    seat.user_data().insert_if_missing(move || xkb_config);
 
     seat
 }
@@ -70,9 +249,17 @@ pub fn add_seat(dh: &DisplayHandle, name: String) -> Seat<State> {
 impl State {
     pub fn process_input_event<B: InputBackend>(
         &mut self,
-        _dh: &DisplayHandle,
+        dh: &DisplayHandle,
         event: InputEvent<B>,
     ) {
+        // Devices coming and going is not the user doing anything
+        if !matches!(
+            event,
+            InputEvent::DeviceAdded { .. } | InputEvent::DeviceRemoved { .. }
+        ) {
+            self.notify_activity();
+        }
+
         match event {
             InputEvent::DeviceAdded { device } => {
                 let seat = &mut self.common.last_active_seat;
@@ -100,11 +287,56 @@ impl State {
                     }
                 }
             }
-            InputEvent::Keyboard { event: _ } => {}
-            InputEvent::PointerMotion { event: _ } => {}
-            InputEvent::PointerMotionAbsolute { event: _ } => {}
-            InputEvent::PointerButton { event: _ } => {}
-            InputEvent::PointerAxis { event: _ } => {}
+            InputEvent::Keyboard { event } => {
+                // A virtual keyboard may have left its keymap on the seat
+                self.restore_seat_keymap(dh);
+                self.keyboard_key(dh, event.key_code(), event.state(), event.time())
+            }
+            InputEvent::PointerMotion { event } => {
+                self.pointer_motion_relative(dh, event.delta(), event.delta_unaccel(), event.time())
+            }
+            InputEvent::PointerMotionAbsolute { event } => {
+                let seat = self.common.last_active_seat.clone();
+                let geometry = active_output(&seat, &self.common).geometry();
+                let location = event.position_transformed(geometry.size) + geometry.loc.to_f64();
+                self.pointer_motion(dh, &seat, location, event.time());
+            }
+            InputEvent::PointerButton { event } => {
+                self.pointer_button(dh, event.button_code(), event.state(), event.time())
+            }
+            InputEvent::PointerAxis { event } => {
+                self.pointer_axis(dh, ScrollEvent::from_event::<B>(&event))
+            }
+            InputEvent::GestureSwipeBegin { event } => {
+                self.gesture_begin(GestureKind::Swipe, event.fingers())
+            }
+            InputEvent::GestureSwipeUpdate { event } => {
+                self.gesture_update(GestureUpdate::Update {
+                    dx: event.delta_x(),
+                    dy: event.delta_y(),
+                    scale: 1.0,
+                    rotation: 0.0,
+                })
+            }
+            InputEvent::GestureSwipeEnd { event } => self.gesture_update(GestureUpdate::End {
+                cancelled: event.cancelled(),
+            }),
+            InputEvent::GesturePinchBegin { event } => {
+                self.gesture_begin(GestureKind::Pinch, event.fingers())
+            }
+            InputEvent::GesturePinchUpdate { event } => {
+                self.gesture_update(GestureUpdate::Update {
+                    dx: event.delta_x(),
+                    dy: event.delta_y(),
+                    scale: event.scale(),
+                    rotation: event.rotation(),
+                })
+            }
+            InputEvent::GesturePinchEnd { event } => self.gesture_update(GestureUpdate::End {
+                cancelled: event.cancelled(),
+            }),
+            InputEvent::GestureHoldBegin { event: _ } => {}
+            InputEvent::GestureHoldEnd { event: _ } => {}
             InputEvent::TouchDown { event: _ } => {}
             InputEvent::TouchMotion { event: _ } => {}
             InputEvent::TouchUp { event: _ } => {}
@@ -117,4 +349,562 @@ impl State {
             InputEvent::Special(_) => {}
         }
     }
+
+    /// Handles a key from any keyboard, real or virtual
+    pub fn keyboard_key(&mut self, dh: &DisplayHandle, keycode: u32, state: KeyState, time: u32) {
+        let seat = self.common.last_active_seat.clone();
+        let serial = SERIAL_COUNTER.next_serial();
+        if let Some(keyboard) = seat.get_keyboard() {
+            // The focused surface may have gone away since focus last changed
+            self.common.update_shortcuts_inhibit();
+            let locked = self.common.session_lock_state.is_locked();
+            let inhibit = &self.common.keyboard_shortcuts_inhibit_state;
+            let inhibited = inhibit.is_inhibited();
+            let escape = inhibit.escape();
+            let bindings = &self.common.bindings;
+            let supressed = seat.user_data().get::<SupressedKeys>().unwrap();
+            let triggered = keyboard.input(
+                dh,
+                keycode,
+                state,
+                serial,
+                time,
+                |modifiers, handle| match state {
+                    // Bindings wait until the session is unlocked
+                    KeyState::Pressed if locked => FilterResult::Forward,
+                    // Only the escape combination is kept from an inhibiting client
+                    KeyState::Pressed if inhibited => match escape {
+                        Some(escape) if escape.matches(modifiers, handle.raw_syms()) => {
+                            supressed.add(keycode, None);
+                            FilterResult::Intercept(None)
+                        }
+                        _ => FilterResult::Forward,
+                    },
+                    KeyState::Pressed => match bindings.find_key(modifiers, handle.raw_syms()) {
+                        Some(binding) => {
+                            supressed.add(keycode, Some(binding.id));
+                            FilterResult::Intercept(Some(binding.id))
+                        }
+                        None => FilterResult::Forward,
+                    },
+                    KeyState::Released => match supressed.remove(keycode) {
+                        Some(id) => FilterResult::Intercept(id),
+                        None => FilterResult::Forward,
+                    },
+                },
+            );
+
+            match triggered {
+                Some(Some(id)) => self.key_binding_triggered(id, keycode, state),
+                Some(None) if state == KeyState::Pressed => self.common.escape_shortcuts_inhibit(),
+                None if state == KeyState::Pressed => {
+                    Self::record_input(dh, &seat, serial, keyboard.current_focus())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the pointer by a delta, telling relative pointers about it first
+    pub fn pointer_motion_relative(
+        &mut self,
+        dh: &DisplayHandle,
+        delta: Point<f64, Logical>,
+        delta_unaccel: Point<f64, Logical>,
+        time: u32,
+    ) {
+        let seat = self.common.last_active_seat.clone();
+        if let Some(pointer) = seat.get_pointer() {
+            if let Some(surface) = self.common.pointer_focus(&seat) {
+                self.common.relative_pointer_state.send_motion(
+                    dh,
+                    &surface,
+                    time as u64 * 1000,
+                    delta,
+                    delta_unaccel,
+                );
+            }
+            let location = pointer.current_location() + delta;
+            self.pointer_motion(dh, &seat, location, time);
+        }
+    }
+
+    /// Handles a button from any pointer, real or virtual
+    pub fn pointer_button(
+        &mut self,
+        dh: &DisplayHandle,
+        button: u32,
+        state: ButtonState,
+        time: u32,
+    ) {
+        let seat = self.common.last_active_seat.clone();
+        let serial = SERIAL_COUNTER.next_serial();
+        let locked = self.common.session_lock_state.is_locked();
+        if !locked && self.pointer_button_binding(&seat, button, state, serial) {
+            return;
+        }
+        if let Some(pointer) = seat.get_pointer() {
+            if state == ButtonState::Pressed {
+                let output = active_output(&seat, &self.common);
+                let under = self
+                    .common
+                    .surface_under(&output, pointer.current_location())
+                    .map(|(surface, _)| surface);
+                if locked {
+                    self.common
+                        .set_focus(dh, under.as_ref(), &seat, Some(serial));
+                }
+                Self::record_input(dh, &seat, serial, under);
+            }
+            if state == ButtonState::Pressed && !pointer.is_grabbed() && !locked {
+                let location = pointer.current_location();
+                match self.common.shell.decoration_under(location) {
+                    Some((window, part)) => {
+                        self.decoration_click(dh, &seat, window, part, button, serial)
+                    }
+                    None if self.common.shell.fallback_active => {
+                        self.fallback_click(dh, &seat, location, serial)
+                    }
+                    None => {}
+                }
+            }
+
+            pointer.button(
+                self,
+                dh,
+                &ButtonEvent {
+                    button,
+                    state,
+                    serial,
+                    time,
+                },
+            );
+        }
+    }
+
+    /// Handles a scroll from any pointer, real or virtual
+    pub fn pointer_axis(&mut self, dh: &DisplayHandle, event: ScrollEvent) {
+        let seat = self.common.last_active_seat.clone();
+        if !self.common.session_lock_state.is_locked() && self.pointer_axis_binding(&seat, &event) {
+            return;
+        }
+
+        let source = event.source;
+        let mut frame = AxisFrame::new(event.time).source(source);
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            let amount = event
+                .amount(axis)
+                .or_else(|| event.amount_discrete(axis).map(|steps| steps * 3.0))
+                .unwrap_or(0.0);
+            if event.stops(axis) {
+                frame = frame.stop(axis);
+            } else if amount != 0.0 {
+                frame = frame.value(axis, amount);
+                if let Some(steps) = event.amount_discrete(axis) {
+                    frame = frame.discrete(axis, steps as i32);
+                }
+            }
+        }
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.axis(self, dh, frame);
+        }
+    }
+
+    fn key_binding_triggered(&mut self, id: u32, keycode: u32, state: KeyState) {
+        let binding = match self.common.bindings.key(id) {
+            Some(binding) => binding.clone(),
+            None => return,
+        };
+        let runtime_sender = self.common.shell.active_workspace().runtime_sender.clone();
+
+        match state {
+            KeyState::Pressed => {
+                if binding.on_press {
+                    runtime_sender
+                        .send(RuntimeMessage::Binding {
+                            id,
+                            state: BindingState::Pressed,
+                        })
+                        .unwrap();
+                }
+                if binding.repeat {
+                    self.stop_key_repeat();
+                    let token = self
+                        .common
+                        .event_loop_handle
+                        .insert_source(Timer::from_duration(REPEAT_DELAY), move |_, _, _| {
+                            runtime_sender
+                                .send(RuntimeMessage::Binding {
+                                    id,
+                                    state: BindingState::Repeated,
+                                })
+                                .unwrap();
+                            TimeoutAction::ToDuration(REPEAT_INTERVAL)
+                        })
+                        .expect("Failed to initialise key repeat timer");
+                    self.common.bindings.repeat = Some((keycode, token));
+                }
+            }
+            KeyState::Released => {
+                if matches!(self.common.bindings.repeat, Some((key, _)) if key == keycode) {
+                    self.stop_key_repeat();
+                }
+                if binding.on_release {
+                    runtime_sender
+                        .send(RuntimeMessage::Binding {
+                            id,
+                            state: BindingState::Released,
+                        })
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    pub fn stop_key_repeat(&mut self) {
+        if let Some((_, token)) = self.common.bindings.repeat.take() {
+            self.common.event_loop_handle.remove(token);
+        }
+    }
+
+    fn record_input(
+        dh: &DisplayHandle,
+        seat: &Seat<State>,
+        serial: Serial,
+        surface: Option<WlSurface>,
+    ) {
+        if let Some(client) = surface.and_then(|surface| dh.get_client(surface.id()).ok()) {
+            let recent = seat.user_data().get::<RecentInput>().unwrap();
+            recent.add(serial, client.id());
+        }
+    }
+
+    fn modifier_state(seat: &Seat<State>) -> ModifiersState {
+        seat.get_keyboard()
+            .map(|keyboard| keyboard.modifier_state())
+            .unwrap_or_default()
+    }
+
+    /// Runs the button binding matching this press, or finishes the one that claimed it.
+    /// Returns true if the button should not reach clients.
+    fn pointer_button_binding(
+        &mut self,
+        seat: &Seat<State>,
+        button: u32,
+        state: ButtonState,
+        serial: Serial,
+    ) -> bool {
+        let supressed = seat.user_data().get::<SupressedButtons>().unwrap();
+        let runtime_sender = self.common.shell.active_workspace().runtime_sender.clone();
+
+        if state == ButtonState::Released {
+            return match supressed.remove(button) {
+                Some(id) => {
+                    runtime_sender
+                        .send(RuntimeMessage::Binding {
+                            id,
+                            state: BindingState::Released,
+                        })
+                        .unwrap();
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let pointer = match seat.get_pointer() {
+            Some(pointer) if !pointer.is_grabbed() => pointer,
+            _ => return false,
+        };
+        let binding = match self
+            .common
+            .bindings
+            .find_button(&Self::modifier_state(seat), button)
+        {
+            Some(binding) => binding.clone(),
+            None => return false,
+        };
+
+        match binding.action {
+            ButtonAction::Event => {
+                supressed.add(button, binding.id);
+                runtime_sender
+                    .send(RuntimeMessage::Binding {
+                        id: binding.id,
+                        state: BindingState::Pressed,
+                    })
+                    .unwrap();
+                true
+            }
+            // The press still goes through the pointer, where the new grab swallows it
+            ButtonAction::Move | ButtonAction::Resize => {
+                self.start_binding_grab(
+                    seat,
+                    binding.action,
+                    button,
+                    pointer.current_location(),
+                    serial,
+                );
+                false
+            }
+        }
+    }
+
+    fn start_binding_grab(
+        &mut self,
+        seat: &Seat<State>,
+        action: ButtonAction,
+        button: u32,
+        location: Point<f64, Logical>,
+        serial: Serial,
+    ) {
+        let output = active_output(seat, &self.common);
+        let workspace = self.common.shell.active_workspace_mut();
+        let window = match workspace.space.window_under(location).cloned() {
+            Some(window) if workspace.get_fullscreen(&output) != Some(&window) => window,
+            _ => return,
+        };
+        let window_location = workspace.space.window_location(&window).unwrap();
+        workspace.space.raise_window(&window, true);
+
+        let start_data = PointerGrabStartData {
+            focus: None,
+            button,
+            location,
+        };
+        let pointer = seat.get_pointer().unwrap();
+        if action == ButtonAction::Move {
+            pointer.set_grab(
+                MoveSurfaceGrab {
+                    start_data,
+                    window,
+                    initial_window_location: window_location,
+                },
+                serial,
+                Focus::Clear,
+            );
+        } else {
+            let mut geometry = window.geometry();
+            geometry.loc = window_location;
+
+            // Resize from whichever corner is closest to the pointer
+            let center = geometry.loc.to_f64() + geometry.size.to_f64().downscale(2.0);
+            let edges = match (location.x < center.x, location.y < center.y) {
+                (true, true) => ResizeEdge::TopLeft,
+                (false, true) => ResizeEdge::TopRight,
+                (true, false) => ResizeEdge::BottomLeft,
+                (false, false) => ResizeEdge::BottomRight,
+            };
+
+            pointer.set_grab(
+                ResizeSurfaceGrab {
+                    start_data,
+                    window,
+                    edges,
+                    initial_window_geometry: geometry,
+                },
+                serial,
+                Focus::Clear,
+            );
+        }
+    }
+
+    /// Returns true if a binding claimed this scroll, so it should not reach clients
+    ///
+    /// Wheels trigger a binding once per event, smooth scrolling once per `SCROLL_STEP` scrolled.
+    fn pointer_axis_binding(&mut self, seat: &Seat<State>, event: &ScrollEvent) -> bool {
+        // A stopped scroll, such as a finger lifted from the touchpad, does not carry leftovers over
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            if event.stops(axis) {
+                self.common.bindings.scroll[ScrollEvent::index(axis)] = 0.0;
+            }
+        }
+
+        let discrete = [Axis::Horizontal, Axis::Vertical]
+            .into_iter()
+            .any(|axis| event.amount_discrete(axis).is_some());
+        let amount = |axis| {
+            if discrete {
+                event.amount_discrete(axis).unwrap_or(0.0)
+            } else {
+                event.amount(axis).unwrap_or(0.0)
+            }
+        };
+        let (horizontal, vertical) = (amount(Axis::Horizontal), amount(Axis::Vertical));
+        let (axis, direction, delta) = if vertical < 0.0 {
+            (Axis::Vertical, AxisDirection::Up, vertical)
+        } else if vertical > 0.0 {
+            (Axis::Vertical, AxisDirection::Down, vertical)
+        } else if horizontal < 0.0 {
+            (Axis::Horizontal, AxisDirection::Left, horizontal)
+        } else if horizontal > 0.0 {
+            (Axis::Horizontal, AxisDirection::Right, horizontal)
+        } else {
+            return false;
+        };
+
+        let on_root = match seat.get_pointer() {
+            Some(pointer) => self
+                .common
+                .shell
+                .active_workspace()
+                .space
+                .window_under(pointer.current_location())
+                .is_none(),
+            None => return false,
+        };
+
+        let id =
+            match self
+                .common
+                .bindings
+                .find_axis(&Self::modifier_state(seat), direction, on_root)
+            {
+                Some(binding) => binding.id,
+                None => return false,
+            };
+
+        let steps = if discrete {
+            1
+        } else {
+            let scroll = &mut self.common.bindings.scroll[ScrollEvent::index(axis)];
+            // Turning around starts over instead of first undoing the way scrolled so far
+            if *scroll * delta < 0.0 {
+                *scroll = 0.0;
+            }
+            *scroll += delta;
+            let steps = (scroll.abs() / SCROLL_STEP).floor();
+            *scroll -= steps * SCROLL_STEP * delta.signum();
+            steps as u32
+        };
+        for _ in 0..steps {
+            self.common
+                .shell
+                .active_workspace()
+                .runtime_sender
+                .send(RuntimeMessage::Binding {
+                    id,
+                    state: BindingState::Pressed,
+                })
+                .unwrap();
+        }
+        true
+    }
+
+    fn gesture_begin(&mut self, kind: GestureKind, fingers: u32) {
+        let seat = &self.common.last_active_seat;
+        self.common.bindings.gesture = self
+            .common
+            .bindings
+            .find_gesture(&Self::modifier_state(seat), kind, fingers)
+            .filter(|_| !self.common.session_lock_state.is_locked())
+            .map(|binding| binding.id);
+        self.gesture_update(GestureUpdate::Begin);
+    }
+
+    fn gesture_update(&mut self, update: GestureUpdate) {
+        if let Some(id) = self.common.bindings.gesture {
+            self.common
+                .shell
+                .active_workspace()
+                .runtime_sender
+                .send(RuntimeMessage::Gesture { id, update })
+                .unwrap();
+        }
+        if let GestureUpdate::End { .. } = update {
+            self.common.bindings.gesture = None;
+        }
+    }
+
+    pub fn pointer_motion(
+        &mut self,
+        dh: &DisplayHandle,
+        seat: &Seat<State>,
+        mut location: Point<f64, Logical>,
+        time: u32,
+    ) {
+        // The constraint of the surface the pointer is on decides where it may go
+        if let Some(pointer) = seat.get_pointer() {
+            let current = pointer.current_location();
+            let under = self
+                .common
+                .surface_under(&active_output(seat, &self.common), current);
+            // Lifting a lock moves the pointer where the client hinted instead
+            if let Some(unlock) = self
+                .common
+                .pointer_constraints_state
+                .update(under.as_ref(), current)
+            {
+                self.warp_to_hint(dh, unlock);
+                return;
+            }
+            location = match self
+                .common
+                .pointer_constraints_state
+                .constrain(current, location)
+            {
+                Some(location) => location,
+                None => return,
+            };
+        }
+
+        let output = self
+            .common
+            .shell
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(location))
+            .cloned()
+            .unwrap_or_else(|| active_output(seat, &self.common));
+        let geometry = output.geometry();
+        let location = Point::<f64, Logical>::from((
+            location.x.clamp(
+                geometry.loc.x as f64,
+                (geometry.loc.x + geometry.size.w - 1) as f64,
+            ),
+            location.y.clamp(
+                geometry.loc.y as f64,
+                (geometry.loc.y + geometry.size.h - 1) as f64,
+            ),
+        ));
+
+        let under = self.common.surface_under(&output, location);
+        // Only a constraint the pointer just entered can apply here, nothing can be unlocked
+        self.common
+            .pointer_constraints_state
+            .update(under.as_ref(), location);
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.motion(
+                self,
+                dh,
+                &MotionEvent {
+                    location,
+                    focus: under,
+                    serial: SERIAL_COUNTER.next_serial(),
+                    time,
+                },
+            );
+        }
+    }
+
+    /// Focuses and raises the window that was clicked
+    fn fallback_click(
+        &mut self,
+        dh: &DisplayHandle,
+        seat: &Seat<State>,
+        location: Point<f64, Logical>,
+        serial: Serial,
+    ) {
+        let workspace = self.common.shell.active_workspace_mut();
+        let window = workspace.space.window_under(location).cloned();
+        if let Some(window) = &window {
+            workspace.space.raise_window(window, true);
+        }
+
+        self.common.set_focus(
+            dh,
+            window.as_ref().map(|window| window.toplevel().wl_surface()),
+            seat,
+            Some(serial),
+        );
+    }
 }
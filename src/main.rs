@@ -1,17 +1,18 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::{error::Error, ffi::OsString, sync::Arc};
+use std::{error::Error, ffi::OsString, path::PathBuf, sync::Arc, time::Instant};
 
 use anyhow::Context;
 use calloop::channel::{channel, Event, Sender};
 use smithay::{
+    desktop::layer_map_for_output,
     reexports::{
         calloop::{generic::Generic, EventLoop, Interest, Mode, PostAction},
         wayland_server::Display,
     },
-    wayland::socket::ListeningSocketSource,
+    wayland::{output::Scale, socket::ListeningSocketSource},
 };
-use state::{Data, LoopData, State};
+use state::{output::OutputExt, Data, LoopData, State};
 
 use crate::log::init_logger;
 use crate::runtime::messages::{CompositorMessage, RuntimeMessage};
@@ -25,6 +26,14 @@ mod state;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let log = init_logger()?;
+
+    // `electrum check [main.ts]` checks a configuration instead of starting the compositor
+    if std::env::args().nth(1).as_deref() == Some("check") {
+        let code = runtime::check::run(std::env::args_os().nth(2).map(PathBuf::from));
+        std::mem::drop(log);
+        std::process::exit(code);
+    }
+
     slog_scope::info!("Starting up");
 
     let mut event_loop =
@@ -34,7 +43,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let compositor_sender = init_compositor_channel(&mut event_loop);
 
-    let runtime = runtime::Runtime::new(compositor_sender);
+    let runtime = runtime::Runtime::new(compositor_sender, socket.clone());
     let runtime_sender = runtime.runtime_sender.clone();
     runtime.run_with_calloop(&mut event_loop);
 
@@ -89,6 +98,101 @@ fn init_compositor_channel(event_loop: &mut EventLoop<LoopData>) -> Sender<Compo
                     .send(RuntimeMessage::Ping)
                     .unwrap();
             }
+            Event::Msg(CompositorMessage::Reloaded) => {
+                data.state.reset_script_state(&data.display.handle(), false);
+
+                let shell = &data.state.common.shell;
+                let runtime_sender = &shell.active_workspace().runtime_sender;
+                for output in shell.outputs() {
+                    runtime_sender
+                        .send(RuntimeMessage::OutputAdded {
+                            output: output.clone(),
+                        })
+                        .unwrap();
+                }
+                for window in shell.windows() {
+                    runtime_sender
+                        .send(RuntimeMessage::WindowMapped {
+                            window: window.clone(),
+                        })
+                        .unwrap();
+                }
+                let inhibit = &data.state.common.keyboard_shortcuts_inhibit_state;
+                if inhibit.is_inhibited() {
+                    runtime_sender
+                        .send(RuntimeMessage::ShortcutsInhibited { inhibited: true })
+                        .unwrap();
+                }
+            }
+            Event::Msg(CompositorMessage::ScriptStopped) => {
+                slog_scope::warn!("No script is running, using the built-in window manager");
+                data.state.reset_script_state(&data.display.handle(), true);
+            }
+            Event::Msg(CompositorMessage::ScriptError) => {
+                data.state.common.script_error = Some(Instant::now());
+            }
+            Event::Msg(CompositorMessage::Bind(binding)) => {
+                data.state.common.bindings.add(binding);
+            }
+            Event::Msg(CompositorMessage::Unbind(id)) => {
+                data.state.common.bindings.remove(id);
+            }
+            Event::Msg(CompositorMessage::SetBindingMode(mode)) => {
+                data.state.stop_key_repeat();
+                data.state.common.bindings.set_mode(mode);
+            }
+            Event::Msg(CompositorMessage::SetBeforeMapHook(enabled)) => {
+                data.state.common.shell.before_map_hook = enabled;
+                if !enabled {
+                    data.state.flush_initial_layouts(&data.display.handle());
+                }
+            }
+            Event::Msg(CompositorMessage::InitialLayout { id, layout }) => {
+                data.state
+                    .apply_initial_layout(&data.display.handle(), id, Some(layout));
+            }
+            Event::Msg(CompositorMessage::AddWindowRule { id, rule }) => {
+                data.state.common.shell.rules.add(id, rule);
+            }
+            Event::Msg(CompositorMessage::RemoveWindowRule(id)) => {
+                data.state.common.shell.rules.remove(id);
+            }
+            Event::Msg(CompositorMessage::SetDecorationPolicy(policy)) => {
+                data.state.set_decoration_policy(policy);
+            }
+            Event::Msg(CompositorMessage::SetOutputScale { output, scale }) => {
+                let shell = &mut data.state.common.shell;
+                match shell.outputs().find(|o| o.name() == output).cloned() {
+                    Some(output) => {
+                        output.change_current_state(
+                            None,
+                            None,
+                            Some(Scale::Fractional(scale)),
+                            None,
+                        );
+                        layer_map_for_output(&output).arrange(&data.display.handle());
+                        shell.refresh_outputs();
+                    }
+                    None => slog_scope::warn!("Script set the scale of unknown output {}", output),
+                }
+            }
+            Event::Msg(CompositorMessage::SetIdleTimeout(timeout)) => {
+                data.state.set_idle_timeout(timeout);
+            }
+            Event::Msg(CompositorMessage::SetShortcutsInhibitEscape(combo)) => {
+                data.state
+                    .common
+                    .keyboard_shortcuts_inhibit_state
+                    .set_escape(combo);
+            }
+            Event::Msg(CompositorMessage::RequestOutputFrame(name)) => {
+                match data.state.common.shell.outputs().find(|o| o.name() == name) {
+                    Some(output) => output.request_frame(),
+                    None => {
+                        slog_scope::warn!("Script requested a frame of unknown output {}", name)
+                    }
+                }
+            }
             Event::Closed => todo!(),
         })
         .expect("Failed to initalize compositor message channel");
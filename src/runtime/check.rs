@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use deno_core::serde_json::{self, Map, Value};
+use deno_core::{JsRuntime, ModuleSpecifier, RuntimeOptions};
+
+use super::errors::format_error;
+use super::module::{TypescriptModuleLoader, IMPORT_MAP_FILE};
+use super::permissions::ScriptPermissions;
+use super::stdlib;
+use super::watcher::ModuleWatcher;
+
+/// Checks a configuration script without starting the compositor, for `electrum check`.
+///
+/// The module graph is loaded exactly as the compositor would, catching missing modules and
+/// syntax errors. Type errors are reported by `deno check` against the bundled declarations, so
+/// Deno has to be installed. Returns the process exit code.
+pub fn run(path: Option<PathBuf>) -> i32 {
+    let path = path.unwrap_or_else(super::config_path);
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("error: {:?}: {}", path, err);
+            return 1;
+        }
+    };
+
+    if let Err(err) = load_graph(&path) {
+        eprintln!("error: {}", format_error(&err));
+        return 1;
+    }
+
+    match type_check(&path) {
+        Ok(true) => {
+            eprintln!("{:?} is fine", path);
+            0
+        }
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("error: could not type check: {:#}", err);
+            1
+        }
+    }
+}
+
+fn load_graph(path: &Path) -> Result<(), Error> {
+    let script_dir = path.parent().unwrap();
+    let main_module =
+        ModuleSpecifier::from_file_path(path).map_err(|_| anyhow!("Invalid path {:?}", path))?;
+
+    // Nothing is reloaded here, the watcher only has to exist for the loader
+    let (watcher, _) = ModuleWatcher::new()?;
+    let loader = TypescriptModuleLoader::new(
+        Arc::new(watcher),
+        script_dir,
+        ScriptPermissions::load(script_dir).to_permissions(script_dir),
+    );
+    // Checking must not change the configuration
+    loader.verify_lockfile_only();
+    let loader = Rc::new(loader);
+    let mut runtime = JsRuntime::new(RuntimeOptions {
+        source_map_getter: Some(Box::new(loader.source_maps())),
+        module_loader: Some(loader),
+        ..Default::default()
+    });
+
+    // Loading instantiates every module without evaluating any of them
+    futures::executor::block_on(runtime.load_main_module(&main_module, None))?;
+    Ok(())
+}
+
+/// Runs `deno check` with the built-in modules written out and mapped in.
+/// Returns whether the script type checks.
+fn type_check(path: &Path) -> Result<bool, Error> {
+    let script_dir = path.parent().unwrap();
+    // Removed again when dropped
+    let temp_dir = tempfile::Builder::new()
+        .prefix("electrum-check-")
+        .tempdir()?;
+    let dir = temp_dir.path();
+
+    let mut imports = Map::new();
+    let mut types = None;
+    for (name, file_name, code) in stdlib::files() {
+        let file = dir.join(&file_name);
+        std::fs::write(&file, code)?;
+        let url = Value::String(ModuleSpecifier::from_file_path(&file).unwrap().to_string());
+        imports.insert(format!("electrum/{}", name), url.clone());
+        imports.insert(format!("{}:{}", stdlib::SCHEME, name), url);
+        if name == "types" {
+            types = Some(file);
+        }
+    }
+
+    let mut import_map = Map::new();
+    if let Some(Value::Object(user_map)) = read_import_map(script_dir)? {
+        for (key, value) in user_map {
+            match (key.as_str(), value) {
+                ("imports", Value::Object(user_imports)) => imports.extend(user_imports),
+                (_, value) => {
+                    import_map.insert(key, value);
+                }
+            }
+        }
+    }
+    import_map.insert("imports".to_string(), Value::Object(imports));
+
+    let import_map_path = dir.join(IMPORT_MAP_FILE);
+    std::fs::write(&import_map_path, serde_json::to_vec(&import_map)?)?;
+
+    let status = Command::new("deno")
+        .arg("check")
+        .arg("--quiet")
+        .arg(format!("--import-map={}", import_map_path.display()))
+        .arg(path)
+        .args(types)
+        .status()
+        .map_err(|err| anyhow!("Failed to run deno: {}", err))?;
+    Ok(status.success())
+}
+
+/// The script's own import map, with addresses made absolute as it is checked from elsewhere
+fn read_import_map(script_dir: &Path) -> Result<Option<Value>, Error> {
+    let path = script_dir.join(IMPORT_MAP_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(None),
+    };
+    let base = ModuleSpecifier::from_file_path(&path).unwrap();
+
+    let rebase = |map: &mut Map<String, Value>| {
+        for value in map.values_mut() {
+            if let Value::String(address) = value {
+                if let Ok(url) = base.join(address) {
+                    *address = url.to_string();
+                }
+            }
+        }
+    };
+
+    let mut import_map: Value = serde_json::from_str(&contents)?;
+    if let Some(Value::Object(imports)) = import_map.get_mut("imports") {
+        rebase(imports);
+    }
+    if let Some(Value::Object(scopes)) = import_map.get_mut("scopes") {
+        let rebased = std::mem::take(scopes)
+            .into_iter()
+            .map(|(scope, mut map)| {
+                if let Value::Object(map) = &mut map {
+                    rebase(map);
+                }
+                let scope = base
+                    .join(&scope)
+                    .map(|url| url.to_string())
+                    .unwrap_or(scope);
+                (scope, map)
+            })
+            .collect();
+        *scopes = rebased;
+    }
+    Ok(Some(import_map))
+}
@@ -0,0 +1,194 @@
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::process::Command;
+use std::thread;
+
+use calloop::channel::Sender;
+use deno_core::error::{AnyError, JsError};
+
+use super::messages::CompositorMessage;
+
+/// Renders an error thrown by a script like Deno does, with its stack mapped back to the
+/// TypeScript sources
+pub fn format_js_error(error: &JsError) -> String {
+    let mut message = error.exception_message.clone();
+
+    if let Some(source_line) = error.source_line.as_deref().map(str::trim_end) {
+        if !source_line.trim().is_empty() {
+            let _ = write!(message, "\n    {}", source_line);
+            let column = error
+                .source_line_frame_index
+                .and_then(|index| error.frames.get(index))
+                .and_then(|frame| frame.column_number);
+            if let Some(column) = column {
+                let _ = write!(
+                    message,
+                    "\n    {}^",
+                    " ".repeat(column.saturating_sub(1) as usize)
+                );
+            }
+        }
+    }
+
+    for frame in &error.frames {
+        let location = match (&frame.file_name, frame.line_number, frame.column_number) {
+            (Some(file), Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+            (Some(file), _, _) => file.clone(),
+            _ => "<anonymous>".to_string(),
+        };
+        match &frame.function_name {
+            Some(function) => {
+                let _ = write!(message, "\n    at {} ({})", function, location);
+            }
+            None => {
+                let _ = write!(message, "\n    at {}", location);
+            }
+        }
+    }
+
+    if let Some(cause) = &error.cause {
+        let _ = write!(message, "\nCaused by: {}", format_js_error(cause));
+    }
+    message
+}
+
+pub fn format_error(err: &AnyError) -> String {
+    match err.downcast_ref::<JsError>() {
+        Some(error) => format_js_error(error),
+        None => format!("{:#}", err),
+    }
+}
+
+/// `file:line:column` of the innermost frame that belongs to a file
+fn location(err: &AnyError) -> Option<String> {
+    let error = err.downcast_ref::<JsError>()?;
+    error.frames.iter().find_map(|frame| {
+        Some(format!(
+            "{}:{}:{}",
+            frame.file_name.as_ref()?,
+            frame.line_number?,
+            frame.column_number?
+        ))
+    })
+}
+
+/// Shows an uncaught script error on screen through the notification daemon, or has the
+/// compositor point it out itself when there is none
+pub fn notify(socket: &OsStr, compositor_sender: &Sender<CompositorMessage>, err: &AnyError) {
+    let summary = match location(err) {
+        Some(location) => format!("Script error at {}", location),
+        None => "Script error".to_string(),
+    };
+    let body = match err.downcast_ref::<JsError>() {
+        Some(error) => error.exception_message.clone(),
+        None => err.to_string(),
+    };
+
+    let child = Command::new("notify-send")
+        .args(["--app-name=Electrum", "--urgency=critical", "--"])
+        .arg(summary)
+        .arg(body)
+        .env("WAYLAND_DISPLAY", socket)
+        .spawn();
+    let compositor_sender = compositor_sender.clone();
+    match child {
+        Ok(mut child) => {
+            thread::spawn(move || {
+                if !child.wait().map(|status| status.success()).unwrap_or(false) {
+                    slog_scope::debug!("notify-send failed to show the script error");
+                    let _ = compositor_sender.send(CompositorMessage::ScriptError);
+                }
+            });
+        }
+        Err(err) => {
+            slog_scope::debug!("Failed to show script error notification: {}", err);
+            let _ = compositor_sender.send(CompositorMessage::ScriptError);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deno_core::error::JsStackFrame;
+
+    use super::*;
+
+    fn error(message: &str, frames: Vec<JsStackFrame>) -> JsError {
+        JsError {
+            name: None,
+            message: None,
+            stack: None,
+            cause: None,
+            exception_message: message.to_string(),
+            frames,
+            source_line: None,
+            source_line_frame_index: None,
+            aggregated: None,
+        }
+    }
+
+    fn frame(function: Option<&str>, file: Option<&str>, line: i64, column: i64) -> JsStackFrame {
+        JsStackFrame {
+            function_name: function.map(str::to_string),
+            ..JsStackFrame::from_location(file.map(str::to_string), Some(line), Some(column))
+        }
+    }
+
+    #[test]
+    fn formats_frames() {
+        let error = error(
+            "Uncaught Error: boom",
+            vec![
+                frame(Some("run"), Some("file:///config/main.ts"), 3, 9),
+                frame(None, Some("file:///config/main.ts"), 7, 1),
+                frame(None, None, 0, 0),
+            ],
+        );
+        assert_eq!(
+            format_js_error(&error),
+            "Uncaught Error: boom\n    \
+             at run (file:///config/main.ts:3:9)\n    \
+             at file:///config/main.ts:7:1\n    \
+             at <anonymous>"
+        );
+    }
+
+    #[test]
+    fn points_at_the_source_line() {
+        let mut error = error(
+            "Uncaught TypeError: x is not a function",
+            vec![frame(None, Some("file:///config/main.ts"), 2, 3)],
+        );
+        error.source_line = Some("  x();   ".to_string());
+        error.source_line_frame_index = Some(0);
+        assert_eq!(
+            format_js_error(&error),
+            "Uncaught TypeError: x is not a function\n      x();\n      ^\n    \
+             at file:///config/main.ts:2:3"
+        );
+
+        // Blank lines are left out, and so is the caret without a column
+        error.source_line = Some("   ".to_string());
+        assert_eq!(
+            format_js_error(&error),
+            "Uncaught TypeError: x is not a function\n    at file:///config/main.ts:2:3"
+        );
+        error.source_line = Some("x();".to_string());
+        error.source_line_frame_index = None;
+        assert_eq!(
+            format_js_error(&error),
+            "Uncaught TypeError: x is not a function\n    x();\n    \
+             at file:///config/main.ts:2:3"
+        );
+    }
+
+    #[test]
+    fn includes_the_cause() {
+        let mut outer = error("Uncaught Error: outer", Vec::new());
+        outer.cause = Some(Box::new(error("Error: inner", Vec::new())));
+        assert_eq!(
+            format_js_error(&outer),
+            "Uncaught Error: outer\nCaused by: Error: inner"
+        );
+    }
+}
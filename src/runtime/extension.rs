@@ -1,44 +1,465 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use anyhow::Context;
+use anyhow::bail;
+use calloop::channel::Sender;
 use deno_core::error::AnyError;
-use deno_core::serde::Serialize;
-use deno_core::{Extension, op, OpState};
-use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded};
+use deno_core::serde::{Deserialize, Serialize};
+use deno_core::{include_js_files, op, Extension, OpState};
+use deno_runtime::permissions::Permissions;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::StreamExt;
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::input::bindings::{
+  AxisBinding, Binding, BindingState, ButtonAction, ButtonBinding, GestureBinding, GestureKind,
+  GestureUpdate, KeyBinding, KeyCombo, DEFAULT_MODE,
+};
+use crate::shell::{
+  decorations::DecorationPolicy,
+  layout::InitialLayout,
+  rules::{WindowRule, WindowRuleConfig},
+};
+
+use super::messages::CompositorMessage;
+use super::process::{Processes, SpawnOptions};
 
 #[derive(Serialize, Debug)]
 pub enum Event {
-  Ping
+  Ping,
+  WindowMapped {
+    id: u32,
+    app_id: Option<String>,
+    title: Option<String>,
+  },
+  BeforeMap {
+    id: u32,
+    app_id: Option<String>,
+    title: Option<String>,
+  },
+  OutputAdded {
+    name: String,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+  },
+  WindowUrgent {
+    id: u32,
+  },
+  MoveRequest {
+    id: u32,
+  },
+  ResizeRequest {
+    id: u32,
+    edges: u32,
+  },
+  MaximizeRequest {
+    id: u32,
+    output: String,
+  },
+  UnmaximizeRequest {
+    id: u32,
+  },
+  UnfullscreenRequest {
+    id: u32,
+  },
+  ProcessExited {
+    handle: u32,
+    code: Option<i32>,
+    signal: Option<i32>,
+    restarting: bool,
+  },
+  Binding {
+    id: u32,
+    state: BindingState,
+  },
+  Gesture {
+    id: u32,
+    update: GestureUpdate,
+  },
+  OutputFrame {
+    output: String,
+    time: u32,
+  },
+  Idle {
+    idle: bool,
+  },
+  ShortcutsInhibited {
+    inhibited: bool,
+  },
+}
+
+/// What the extension needs from the compositor
+#[derive(Clone)]
+pub struct ExtensionOptions {
+  pub socket: OsString,
+  pub compositor_sender: Sender<CompositorMessage>,
+}
+
+#[derive(Default)]
+struct NextBindingId(u32);
+
+#[derive(Default)]
+struct NextRuleId(u32);
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBindingOptions {
+  pub combo: String,
+  pub mode: Option<String>,
+  #[serde(default = "default_true")]
+  pub on_press: bool,
+  #[serde(default)]
+  pub on_release: bool,
+  #[serde(default)]
+  pub repeat: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ButtonBindingOptions {
+  pub combo: String,
+  pub mode: Option<String>,
+  #[serde(default = "default_button_action")]
+  pub action: ButtonAction,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AxisBindingOptions {
+  pub combo: String,
+  pub mode: Option<String>,
+  #[serde(default)]
+  pub root: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GestureBindingOptions {
+  pub gesture: GestureKind,
+  pub fingers: u32,
+  #[serde(default)]
+  pub modifiers: String,
+  pub mode: Option<String>,
+}
+
+fn default_true() -> bool {
+  true
+}
+
+fn default_button_action() -> ButtonAction {
+  ButtonAction::Event
+}
+
+fn next_binding_id(state: &mut OpState) -> u32 {
+  let next_id = state.borrow_mut::<NextBindingId>();
+  next_id.0 += 1;
+  next_id.0
+}
+
+fn send_binding(state: &mut OpState, binding: Binding) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::Bind(binding))?;
+  Ok(())
 }
 
 #[op]
 pub async fn op_electrum_poll_events(state: &mut OpState) -> Result<Option<Event>, AnyError> {
-  let mut channel = state.borrow_mut::<Rc<RefCell<UnboundedReceiver<Event>>>>().try_borrow_mut()?;
-  let val = channel.next().await;
-  println!("{:?}", val);
-  Ok(val)
+  let mut channel = state
+    .borrow_mut::<Rc<RefCell<UnboundedReceiver<Event>>>>()
+    .try_borrow_mut()?;
+  let event = channel.next().await;
+  slog_scope::trace!("Sending {:?} to the script", event);
+  Ok(event)
+}
+
+#[op]
+pub fn op_electrum_spawn(state: &mut OpState, options: SpawnOptions) -> Result<u32, AnyError> {
+  state
+    .borrow_mut::<Permissions>()
+    .run
+    .check(&options.command, Some("electrum.spawn()"))?;
+  Ok(state.borrow_mut::<Processes>().spawn(options)?)
+}
+
+#[op]
+pub fn op_electrum_bind_key(
+  state: &mut OpState,
+  options: KeyBindingOptions,
+) -> Result<u32, AnyError> {
+  let combo = options.combo.parse()?;
+  let id = next_binding_id(state);
+  send_binding(
+    state,
+    Binding::Key(KeyBinding {
+      id,
+      combo,
+      mode: options.mode.unwrap_or_else(|| DEFAULT_MODE.to_string()),
+      on_press: options.on_press,
+      on_release: options.on_release,
+      repeat: options.repeat,
+    }),
+  )?;
+  Ok(id)
+}
+
+#[op]
+pub fn op_electrum_bind_button(
+  state: &mut OpState,
+  options: ButtonBindingOptions,
+) -> Result<u32, AnyError> {
+  let combo = options.combo.parse()?;
+  let id = next_binding_id(state);
+  send_binding(
+    state,
+    Binding::Button(ButtonBinding {
+      id,
+      combo,
+      mode: options.mode.unwrap_or_else(|| DEFAULT_MODE.to_string()),
+      action: options.action,
+    }),
+  )?;
+  Ok(id)
+}
+
+#[op]
+pub fn op_electrum_bind_axis(
+  state: &mut OpState,
+  options: AxisBindingOptions,
+) -> Result<u32, AnyError> {
+  let combo = options.combo.parse()?;
+  let id = next_binding_id(state);
+  send_binding(
+    state,
+    Binding::Axis(AxisBinding {
+      id,
+      combo,
+      mode: options.mode.unwrap_or_else(|| DEFAULT_MODE.to_string()),
+      root: options.root,
+    }),
+  )?;
+  Ok(id)
+}
+
+#[op]
+pub fn op_electrum_bind_gesture(
+  state: &mut OpState,
+  options: GestureBindingOptions,
+) -> Result<u32, AnyError> {
+  let modifiers = options.modifiers.parse()?;
+  let id = next_binding_id(state);
+  send_binding(
+    state,
+    Binding::Gesture(GestureBinding {
+      id,
+      kind: options.gesture,
+      fingers: options.fingers,
+      modifiers,
+      mode: options.mode.unwrap_or_else(|| DEFAULT_MODE.to_string()),
+    }),
+  )?;
+  Ok(id)
+}
+
+#[op]
+pub fn op_electrum_unbind(state: &mut OpState, id: u32) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::Unbind(id))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_set_binding_mode(state: &mut OpState, mode: String) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::SetBindingMode(mode))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_set_before_map(state: &mut OpState, enabled: bool) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::SetBeforeMapHook(enabled))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_layout_window(
+  state: &mut OpState,
+  id: u32,
+  layout: InitialLayout,
+) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::InitialLayout { id, layout })?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_add_window_rule(
+  state: &mut OpState,
+  rule: WindowRuleConfig,
+) -> Result<u32, AnyError> {
+  let rule = WindowRule::try_from(rule)?;
+  let id = {
+    let next_id = state.borrow_mut::<NextRuleId>();
+    next_id.0 += 1;
+    next_id.0
+  };
+
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::AddWindowRule { id, rule })?;
+  Ok(id)
+}
+
+#[op]
+pub fn op_electrum_remove_window_rule(state: &mut OpState, id: u32) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::RemoveWindowRule(id))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_set_decoration_policy(
+  state: &mut OpState,
+  policy: DecorationPolicy,
+) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::SetDecorationPolicy(policy))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_set_output_scale(
+  state: &mut OpState,
+  output: String,
+  scale: f64,
+) -> Result<(), AnyError> {
+  if !(scale.is_finite() && scale > 0.0) {
+    bail!("Invalid scale {} for output {}", scale, output);
+  }
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::SetOutputScale { output, scale })?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_request_output_frame(
+  state: &mut OpState,
+  output: String,
+) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::RequestOutputFrame(output))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_set_idle_timeout(
+  state: &mut OpState,
+  timeout: Option<u64>,
+) -> Result<(), AnyError> {
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::SetIdleTimeout(
+      timeout.map(Duration::from_millis),
+    ))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_set_shortcuts_inhibit_escape(
+  state: &mut OpState,
+  combo: Option<String>,
+) -> Result<(), AnyError> {
+  let combo = combo.map(|combo| combo.parse::<KeyCombo>()).transpose()?;
+  state
+    .borrow::<Sender<CompositorMessage>>()
+    .send(CompositorMessage::SetShortcutsInhibitEscape(combo))?;
+  Ok(())
+}
+
+#[op]
+pub fn op_electrum_kill(state: &mut OpState, handle: u32) -> Result<(), AnyError> {
+  state.borrow::<Processes>().kill(handle);
+  Ok(())
+}
+
+/// The part of the extension web workers get. Events and bindings belong to the main script,
+/// but workers may start processes, without being told when they exit.
+pub fn worker_extension(socket: OsString) -> Extension {
+  Extension::builder()
+    .js(include_js_files!(
+      prefix "electrum:runtime",
+      "src/runtime/js/process.js",
+      "src/runtime/js/worker.js",
+    ))
+    .state(move |state| {
+      state.put(Processes::new(socket.clone(), None));
+      Ok(())
+    })
+    .ops(vec![op_electrum_spawn::decl(), op_electrum_kill::decl()])
+    .build()
 }
 
 pub struct MainExtensionInstance {
   pub extension: Extension,
-  pub event_sender: UnboundedSender<Event>
+  pub event_sender: UnboundedSender<Event>,
 }
 
-pub fn main_extension() -> MainExtensionInstance {
+pub fn main_extension(options: ExtensionOptions) -> MainExtensionInstance {
   let (sender, reciever) = unbounded();
   let reciever = Rc::new(RefCell::new(reciever));
+  let process_sender = sender.clone();
   let extension = Extension::builder()
+    .js(include_js_files!(
+      prefix "electrum:runtime",
+      "src/runtime/js/process.js",
+      "src/runtime/js/electrum.js",
+    ))
     .state(move |state| {
-        state.put(reciever.clone());
-        Ok(())
-      })
-      .ops(vec![op_electrum_poll_events::decl()])
-      .build();
-    
-      MainExtensionInstance {
-        extension,
-        event_sender: sender
-      }
-}
\ No newline at end of file
+      state.put(reciever.clone());
+      state.put(Processes::new(
+        options.socket.clone(),
+        Some(process_sender.clone()),
+      ));
+      state.put(options.compositor_sender.clone());
+      state.put(NextBindingId::default());
+      state.put(NextRuleId::default());
+      Ok(())
+    })
+    .ops(vec![
+      op_electrum_poll_events::decl(),
+      op_electrum_spawn::decl(),
+      op_electrum_kill::decl(),
+      op_electrum_bind_key::decl(),
+      op_electrum_bind_button::decl(),
+      op_electrum_bind_axis::decl(),
+      op_electrum_bind_gesture::decl(),
+      op_electrum_unbind::decl(),
+      op_electrum_set_binding_mode::decl(),
+      op_electrum_set_before_map::decl(),
+      op_electrum_layout_window::decl(),
+      op_electrum_add_window_rule::decl(),
+      op_electrum_remove_window_rule::decl(),
+      op_electrum_request_output_frame::decl(),
+      op_electrum_set_decoration_policy::decl(),
+      op_electrum_set_output_scale::decl(),
+      op_electrum_set_idle_timeout::decl(),
+      op_electrum_set_shortcuts_inhibit_escape::decl(),
+    ])
+    .build();
+
+  MainExtensionInstance {
+    extension,
+    event_sender: sender,
+  }
+}
@@ -0,0 +1,142 @@
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use deno_runtime::inspector_server::InspectorServer;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9229";
+
+/// The V8 inspector, shared by every worker the script is reloaded into so DevTools can stay
+/// attached to the same address
+#[derive(Clone)]
+pub struct Inspector {
+    pub server: Arc<InspectorServer>,
+    pub break_on_first_statement: bool,
+}
+
+/// Reads `--inspect[=addr]` and `--inspect-brk[=addr]`, or the `ELECTRUM_INSPECT` and
+/// `ELECTRUM_INSPECT_BRK` variables which take an optional address too
+pub fn from_args() -> Option<Inspector> {
+    let (address, break_on_first_statement) =
+        requested(|name| env::var(name).ok(), env::args().skip(1))?;
+    let address = address.as_deref().unwrap_or(DEFAULT_ADDRESS);
+    let address: SocketAddr = match address.parse() {
+        Ok(address) => address,
+        Err(err) => {
+            slog_scope::error!("Invalid inspector address {:?}: {}", address, err);
+            return None;
+        }
+    };
+
+    slog_scope::info!("Starting the script inspector on {}", address);
+    if break_on_first_statement {
+        slog_scope::warn!("The compositor is paused until a debugger attaches");
+    }
+    Some(Inspector {
+        server: Arc::new(InspectorServer::new(address, "electrum".to_string())),
+        break_on_first_statement,
+    })
+}
+
+/// The address asked for, if not the default one, and whether to break on the first statement.
+/// Flags win over the environment, and `0`, `false` or nothing in a variable leave it disabled.
+fn requested(
+    var: impl Fn(&str) -> Option<String>,
+    args: impl IntoIterator<Item = String>,
+) -> Option<(Option<String>, bool)> {
+    let mut requested = None;
+    for (name, break_on_first_statement) in
+        [("ELECTRUM_INSPECT", false), ("ELECTRUM_INSPECT_BRK", true)]
+    {
+        match var(name).as_deref() {
+            None | Some("" | "0" | "false") => {}
+            Some("1" | "true") => requested = Some((None, break_on_first_statement)),
+            Some(address) => {
+                requested = Some((Some(address.to_string()), break_on_first_statement))
+            }
+        }
+    }
+    for arg in args {
+        for (flag, break_on_first_statement) in [("--inspect", false), ("--inspect-brk", true)] {
+            if arg == flag {
+                requested = Some((None, break_on_first_statement));
+            } else if let Some(address) = arg.strip_prefix(&format!("{}=", flag)) {
+                let address = Some(address.to_string()).filter(|address| !address.is_empty());
+                requested = Some((address, break_on_first_statement));
+            }
+        }
+    }
+    requested
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(vars: &[(&str, &str)], args: &[&str]) -> Option<(Option<String>, bool)> {
+        requested(
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            },
+            args.iter().map(|arg| arg.to_string()),
+        )
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert_eq!(parse(&[], &["--other"]), None);
+    }
+
+    #[test]
+    fn reads_flags() {
+        assert_eq!(parse(&[], &["--inspect"]), Some((None, false)));
+        assert_eq!(parse(&[], &["--inspect-brk"]), Some((None, true)));
+        assert_eq!(parse(&[], &["--inspect="]), Some((None, false)));
+        assert_eq!(
+            parse(&[], &["--inspect-brk=0.0.0.0:9000"]),
+            Some((Some("0.0.0.0:9000".to_string()), true))
+        );
+        assert_eq!(parse(&[], &["--inspector"]), None);
+    }
+
+    #[test]
+    fn reads_variables() {
+        assert_eq!(
+            parse(&[("ELECTRUM_INSPECT", "1")], &[]),
+            Some((None, false))
+        );
+        assert_eq!(
+            parse(&[("ELECTRUM_INSPECT", "true")], &[]),
+            Some((None, false))
+        );
+        assert_eq!(
+            parse(&[("ELECTRUM_INSPECT_BRK", "127.0.0.1:9000")], &[]),
+            Some((Some("127.0.0.1:9000".to_string()), true))
+        );
+    }
+
+    #[test]
+    fn falsy_variables_stay_disabled() {
+        for value in ["", "0", "false"] {
+            assert_eq!(parse(&[("ELECTRUM_INSPECT", value)], &[]), None);
+            assert_eq!(parse(&[("ELECTRUM_INSPECT_BRK", value)], &[]), None);
+        }
+    }
+
+    #[test]
+    fn flags_win_over_variables() {
+        assert_eq!(
+            parse(
+                &[("ELECTRUM_INSPECT", "127.0.0.1:9000")],
+                &["--inspect-brk"]
+            ),
+            Some((None, true))
+        );
+        assert_eq!(
+            parse(&[("ELECTRUM_INSPECT", "0")], &["--inspect"]),
+            Some((None, false))
+        );
+    }
+}
@@ -0,0 +1,144 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+  const { spawn } = window.__bootstrap.electrumProcess;
+  const bindings = new Map();
+  const frameRequests = new Map();
+  let beforeMap = null;
+
+  async function pollEvents() {
+    const event = await core.opAsync("op_electrum_poll_events");
+    if (event?.Binding) {
+      const { id, state } = event.Binding;
+      bindings.get(id)?.(state);
+    } else if (event?.Gesture) {
+      const { id, update } = event.Gesture;
+      bindings.get(id)?.(update);
+    } else if (event?.OutputFrame) {
+      const { output, time } = event.OutputFrame;
+      const resolvers = frameRequests.get(output) ?? [];
+      frameRequests.delete(output);
+      resolvers.forEach((resolve) => resolve(time));
+    } else if (event?.BeforeMap) {
+      // Answer even if the hook throws, the window is waiting on it
+      let layout = {};
+      try {
+        layout = beforeMap?.(event.BeforeMap) ?? {};
+      } finally {
+        core.opSync("op_electrum_layout_window", event.BeforeMap.id, layout);
+      }
+    }
+    return event;
+  }
+
+  function onBeforeMap(callback) {
+    beforeMap = callback;
+    core.opSync("op_electrum_set_before_map", callback != null);
+  }
+
+  function binding(id, callback) {
+    bindings.set(id, callback);
+    return {
+      id,
+      unbind: () => {
+        bindings.delete(id);
+        core.opSync("op_electrum_unbind", id);
+      },
+    };
+  }
+
+  function bindKey(combo, callback, options = {}) {
+    const id = core.opSync("op_electrum_bind_key", {
+      combo,
+      mode: options.mode,
+      onPress: options.onPress ?? true,
+      onRelease: options.onRelease ?? false,
+      repeat: options.repeat ?? false,
+    });
+    return binding(id, callback);
+  }
+
+  function bindButton(combo, callback, options = {}) {
+    const id = core.opSync("op_electrum_bind_button", {
+      combo,
+      mode: options.mode,
+      action: options.action ?? "event",
+    });
+    return binding(id, callback ?? (() => {}));
+  }
+
+  function bindAxis(combo, callback, options = {}) {
+    const id = core.opSync("op_electrum_bind_axis", {
+      combo,
+      mode: options.mode,
+      root: options.root ?? false,
+    });
+    return binding(id, callback);
+  }
+
+  function bindGesture(gesture, fingers, callback, options = {}) {
+    const id = core.opSync("op_electrum_bind_gesture", {
+      gesture,
+      fingers,
+      modifiers: options.modifiers ?? "",
+      mode: options.mode,
+    });
+    return binding(id, callback);
+  }
+
+  function addWindowRule(rule) {
+    const id = core.opSync("op_electrum_add_window_rule", rule);
+    return {
+      id,
+      remove: () => core.opSync("op_electrum_remove_window_rule", id),
+    };
+  }
+
+  function requestOutputFrame(output) {
+    return new Promise((resolve) => {
+      if (!frameRequests.has(output)) {
+        frameRequests.set(output, []);
+        core.opSync("op_electrum_request_output_frame", output);
+      }
+      frameRequests.get(output).push(resolve);
+    });
+  }
+
+  function setDecorationPolicy(policy) {
+    core.opSync("op_electrum_set_decoration_policy", policy);
+  }
+
+  function setOutputScale(output, scale) {
+    core.opSync("op_electrum_set_output_scale", output, scale);
+  }
+
+  function setIdleTimeout(timeout) {
+    core.opSync("op_electrum_set_idle_timeout", timeout);
+  }
+
+  function setShortcutsInhibitEscape(combo) {
+    core.opSync("op_electrum_set_shortcuts_inhibit_escape", combo);
+  }
+
+  function setBindingMode(mode) {
+    core.opSync("op_electrum_set_binding_mode", mode);
+  }
+
+  window.electrum = {
+    addWindowRule,
+    bindAxis,
+    bindButton,
+    bindGesture,
+    bindKey,
+    onBeforeMap,
+    pollEvents,
+    requestOutputFrame,
+    setBindingMode,
+    setDecorationPolicy,
+    setIdleTimeout,
+    setOutputScale,
+    setShortcutsInhibitEscape,
+    spawn,
+  };
+})(globalThis);
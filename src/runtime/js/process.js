@@ -0,0 +1,23 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+
+  function spawn(command, args = [], options = {}) {
+    const id = core.opSync("op_electrum_spawn", {
+      command,
+      args,
+      env: options.env ?? {},
+      restart: options.restart ?? false,
+    });
+    return {
+      id,
+      kill: () => core.opSync("op_electrum_kill", id),
+    };
+  }
+
+  // Shared by the main script and web workers, picked up by the file loaded after this one
+  window.__bootstrap.electrumProcess = {
+    spawn,
+  };
+})(globalThis);
@@ -0,0 +1,9 @@
+"use strict";
+
+((window) => {
+  const { spawn } = window.__bootstrap.electrumProcess;
+
+  window.electrum = {
+    spawn,
+  };
+})(globalThis);
@@ -1,9 +1,10 @@
-use super::extension::main_extension;
+use super::errors::format_js_error;
+use super::extension::{main_extension, ExtensionOptions};
+use super::inspector::Inspector;
 use super::module::TypescriptModuleLoader;
+use super::web_worker::{create_web_worker_cb, preload_module_cb, WebWorkerConfig};
 use deno_core::error::AnyError;
-use deno_core::{ModuleSpecifier, Extension};
-use deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel;
-use deno_runtime::deno_web::BlobStore;
+use deno_core::{Extension, ModuleSpecifier};
 use deno_runtime::permissions::Permissions;
 use deno_runtime::worker::{MainWorker, WorkerOptions};
 use deno_runtime::BootstrapOptions;
@@ -12,48 +13,59 @@ use std::{rc::Rc, sync::Arc};
 
 // https://github.com/denoland/deno/blob/main/runtime/examples/hello_runtime.rs
 
-fn get_error_class_name(e: &AnyError) -> &'static str {
-    deno_runtime::errors::get_error_class_name(e).unwrap_or("Error")
+pub fn get_error_class_name(e: &AnyError) -> &'static str {
+    let class = deno_runtime::errors::get_error_class_name(e).unwrap_or("Error");
+    // Every denial passes through here, even if the script goes on to catch it
+    if class == "PermissionDenied" {
+        slog_scope::warn!("Script was denied permission: {}", e);
+    }
+    class
+}
+
+pub fn bootstrap_options() -> BootstrapOptions {
+    BootstrapOptions {
+        args: vec![],
+        cpu_count: num_cpus::get(),
+        debug_flag: false,
+        enable_testing_features: false,
+        location: None,
+        no_color: false,
+        is_tty: false,
+        runtime_version: "x".to_string(),
+        ts_version: "x".to_string(),
+        unstable: false,
+        user_agent: "electrum".to_string(),
+    }
 }
 
-fn options(extensions: Vec<Extension>) -> WorkerOptions {
-    let module_loader = Rc::new(TypescriptModuleLoader);
-    let create_web_worker_cb = Arc::new(|_| {
-        todo!("Web workers are not supported within electrum");
-    });
-    let web_worker_preload_module_cb = Arc::new(|_| {
-        todo!("Web workers are not supported within electrum");
-    });
+fn options(
+    extensions: Vec<Extension>,
+    module_loader: Rc<TypescriptModuleLoader>,
+    inspector: Option<Inspector>,
+    web_workers: WebWorkerConfig,
+) -> WorkerOptions {
+    let source_map_getter = module_loader.source_maps();
 
     WorkerOptions {
-        bootstrap: BootstrapOptions {
-            args: vec![],
-            cpu_count: num_cpus::get(),
-            debug_flag: false,
-            enable_testing_features: false,
-            location: None,
-            no_color: false,
-            is_tty: false,
-            runtime_version: "x".to_string(),
-            ts_version: "x".to_string(),
-            unstable: false,
-            user_agent: "electrum".to_string(),
-        },
+        bootstrap: bootstrap_options(),
         extensions,
         unsafely_ignore_certificate_errors: None,
         root_cert_store: None,
         seed: None,
-        source_map_getter: None,
-        format_js_error_fn: None,
-        web_worker_preload_module_cb,
-        create_web_worker_cb,
-        maybe_inspector_server: None,
-        should_break_on_first_statement: false,
+        source_map_getter: Some(Box::new(source_map_getter)),
+        format_js_error_fn: Some(Arc::new(format_js_error)),
+        web_worker_preload_module_cb: preload_module_cb(),
+        create_web_worker_cb: create_web_worker_cb(web_workers.clone()),
+        should_break_on_first_statement: inspector
+            .as_ref()
+            .map(|inspector| inspector.break_on_first_statement)
+            .unwrap_or(false),
+        maybe_inspector_server: inspector.map(|inspector| inspector.server),
         module_loader,
         get_error_class_fn: Some(&get_error_class_name),
         origin_storage_dir: None,
-        blob_store: BlobStore::default(),
-        broadcast_channel: InMemoryBroadcastChannel::default(),
+        blob_store: web_workers.blob_store,
+        broadcast_channel: web_workers.broadcast_channel,
         shared_array_buffer_store: None,
         compiled_wasm_module_store: None,
         stdio: Default::default(),
@@ -62,15 +74,31 @@ fn options(extensions: Vec<Extension>) -> WorkerOptions {
 
 pub struct MainWorkerInstance {
     pub worker: MainWorker,
-    pub event_sender: UnboundedSender<super::extension::Event>
+    pub event_sender: UnboundedSender<super::extension::Event>,
 }
 
-pub fn new(main_module_path: ModuleSpecifier) -> MainWorkerInstance {
-    let extension_instance = main_extension();
-    let worker = MainWorker::bootstrap_from_options(main_module_path, Permissions::allow_all(), options(vec![extension_instance.extension]));
+pub fn new(
+    main_module_path: ModuleSpecifier,
+    module_loader: Rc<TypescriptModuleLoader>,
+    permissions: Permissions,
+    extension_options: ExtensionOptions,
+    inspector: Option<Inspector>,
+    web_workers: WebWorkerConfig,
+) -> MainWorkerInstance {
+    let extension_instance = main_extension(extension_options);
+    let worker = MainWorker::bootstrap_from_options(
+        main_module_path,
+        permissions,
+        options(
+            vec![extension_instance.extension],
+            module_loader,
+            inspector,
+            web_workers,
+        ),
+    );
 
     MainWorkerInstance {
         worker,
-        event_sender: extension_instance.event_sender
+        event_sender: extension_instance.event_sender,
     }
 }
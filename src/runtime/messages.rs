@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use smithay::{
     desktop::Window,
     reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge,
@@ -8,7 +10,11 @@ use smithay::{
     },
 };
 
-use crate::state::State;
+use crate::{
+    input::bindings::{Binding, BindingState, GestureUpdate, KeyCombo},
+    shell::{decorations::DecorationPolicy, layout::InitialLayout, rules::WindowRule},
+    state::State,
+};
 
 // Messages from the runtime to the compositor
 pub enum RuntimeMessage {
@@ -36,9 +42,73 @@ pub enum RuntimeMessage {
     UnfullscreenRequest {
         window: Window,
     },
+    WindowMapped {
+        window: Window,
+    },
+    /// A new window is waiting for the script to lay it out
+    BeforeMap {
+        window: Window,
+    },
+    OutputAdded {
+        output: Output,
+    },
+    /// A window asked for focus without a valid activation token
+    WindowUrgent {
+        window: Window,
+    },
+    Binding {
+        id: u32,
+        state: BindingState,
+    },
+    Gesture {
+        id: u32,
+        update: GestureUpdate,
+    },
+    /// A frame the script asked for has been presented
+    OutputFrame {
+        output: Output,
+        time: u32,
+    },
+    /// The session went idle or became active again
+    Idle {
+        idle: bool,
+    },
+    /// The focused client started or stopped receiving the keys bindings would claim
+    ShortcutsInhibited {
+        inhibited: bool,
+    },
 }
 
 // Messages from the compositor to the runtime
 pub enum CompositorMessage {
     Ping,
+    /// A fresh script has been booted and needs the current windows and outputs
+    Reloaded,
+    /// There is no running script, the built-in policy should take over
+    ScriptStopped,
+    /// A script error the notification daemon could not show, for the compositor to point out
+    ScriptError,
+    Bind(Binding),
+    Unbind(u32),
+    SetBindingMode(String),
+    SetBeforeMapHook(bool),
+    InitialLayout {
+        id: u32,
+        layout: InitialLayout,
+    },
+    AddWindowRule {
+        id: u32,
+        rule: WindowRule,
+    },
+    RemoveWindowRule(u32),
+    /// The script wants to hear about the next frame presented on the named output
+    RequestOutputFrame(String),
+    SetDecorationPolicy(DecorationPolicy),
+    SetOutputScale {
+        output: String,
+        scale: f64,
+    },
+    SetIdleTimeout(Option<Duration>),
+    /// The combination taking the keys back from a client inhibiting shortcuts, if any
+    SetShortcutsInhibitEscape(Option<KeyCombo>),
 }
@@ -1,64 +1,159 @@
+use std::cell::RefCell;
 use std::env::{self, current_dir};
+use std::ffi::OsString;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::shell::{rules::RULES_FILE, window::WindowExt};
+use crate::state::output::OutputExt;
 use crate::LoopData;
 use calloop::channel::{channel, Channel, Event, Sender};
 use calloop::futures::{Executor, Scheduler};
 use calloop::EventLoop;
 use deno_core::error::AnyError;
 use deno_core::ModuleSpecifier;
+use deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel;
+use deno_runtime::deno_web::BlobStore;
 use deno_runtime::worker::MainWorker;
 
+pub mod check;
+mod errors;
+mod extension;
+mod inspector;
 mod main;
 pub mod messages;
 mod module;
-mod extension;
+mod permissions;
+mod process;
+mod remote;
+mod source_maps;
+mod stdlib;
+mod transpile_cache;
+mod watcher;
+mod web_worker;
 
-use futures::channel::mpsc::UnboundedSender;
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures::future::{select, Either};
+use futures::{pin_mut, StreamExt};
 use messages::{CompositorMessage, RuntimeMessage};
+use module::{TypescriptModuleLoader, IMPORT_MAP_FILE};
+use permissions::{ScriptPermissions, PERMISSIONS_FILE};
+use watcher::ModuleWatcher;
+
+/// Consecutive crashes after which a script is left stopped until it is changed
+const MAX_CRASHES: u32 = 3;
+/// A script crashing after running this long is counted as crashing for the first time
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+type EventSender = Rc<RefCell<Option<UnboundedSender<extension::Event>>>>;
 
 pub struct Runtime {
-    main_worker: MainWorker,
+    main_worker: Option<MainWorker>,
     main_module: ModuleSpecifier,
+    script_dir: PathBuf,
+    extension_options: extension::ExtensionOptions,
+    inspector: Option<inspector::Inspector>,
+    web_workers: web_worker::WebWorkerConfig,
+    module_watcher: Arc<ModuleWatcher>,
+    reload_receiver: UnboundedReceiver<()>,
     runtime_channel: Channel<RuntimeMessage>,
     compositor_sender: Sender<CompositorMessage>,
-    event_sender: UnboundedSender<extension::Event>,
+    event_sender: EventSender,
 
     pub runtime_sender: Sender<RuntimeMessage>,
 }
 
+/// Where the configuration script lives, `src/ts/main.ts` in development builds
+pub fn config_path() -> PathBuf {
+    if cfg!(feature = "devel") {
+        let mut config_path = match env::var("TS_PREFIX") {
+            Ok(x) => PathBuf::from(x),
+            Err(_) => current_dir().unwrap(),
+        };
+        config_path.push("src");
+        config_path.push("ts");
+        config_path.push("main.ts");
+        config_path
+    } else {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("electrum").unwrap();
+        xdg_dirs.get_config_file("main.ts")
+    }
+}
+
 impl Runtime {
-    pub fn new(compositor_sender: Sender<CompositorMessage>) -> Self {
+    pub fn new(compositor_sender: Sender<CompositorMessage>, socket: OsString) -> Self {
         let (runtime_sender, runtime_channel) = channel::<RuntimeMessage>();
-        let mut config_path: PathBuf;
-        if cfg!(feature = "devel") {
-            config_path = match env::var("TS_PREFIX") {
-                Ok(x) => PathBuf::from(x),
-                Err(_) => current_dir().unwrap(),
-            };
-            config_path.push("src");
-            config_path.push("ts");
-            config_path.push("main.ts");
-        } else {
-            let xdg_dirs = xdg::BaseDirectories::with_prefix("electrum").unwrap();
-            config_path = xdg_dirs.get_config_file("main.ts");
-        }
+        let config_path = config_path();
 
         let main_module = deno_core::resolve_path(config_path.to_str().unwrap())
             .expect("failed to resolve main module");
-        let main_worker_instance = main::new(main_module.clone());
+
+        let extension_options = extension::ExtensionOptions {
+            socket,
+            compositor_sender: compositor_sender.clone(),
+        };
+
+        let (module_watcher, reload_receiver) =
+            ModuleWatcher::new().expect("Failed to initialise module watcher");
+        let module_watcher = Arc::new(module_watcher);
+        // Watched up front, so creating a missing main.ts starts it
+        module_watcher.watch(&config_path);
+        let script_dir = config_path.parent().unwrap().to_path_buf();
+        module_watcher.watch(&script_dir.join(PERMISSIONS_FILE));
+        module_watcher.watch(&script_dir.join(IMPORT_MAP_FILE));
+        module_watcher.watch(&script_dir.join(RULES_FILE));
+
+        let inspector = inspector::from_args();
+        let web_workers = web_worker::WebWorkerConfig {
+            script_dir: script_dir.clone(),
+            socket: extension_options.socket.clone(),
+            module_watcher: module_watcher.clone(),
+            blob_store: BlobStore::default(),
+            broadcast_channel: InMemoryBroadcastChannel::default(),
+            inspector_server: inspector.as_ref().map(|inspector| inspector.server.clone()),
+        };
+        let (main_worker, event_sender) = if config_path.exists() {
+            let permissions = ScriptPermissions::load(&script_dir).to_permissions(&script_dir);
+            let main_worker_instance = main::new(
+                main_module.clone(),
+                Rc::new(TypescriptModuleLoader::new(
+                    module_watcher.clone(),
+                    &script_dir,
+                    permissions.clone(),
+                )),
+                permissions,
+                extension_options.clone(),
+                inspector.clone(),
+                web_workers.clone(),
+            );
+            (
+                Some(main_worker_instance.worker),
+                Some(main_worker_instance.event_sender),
+            )
+        } else {
+            slog_scope::warn!("{:?} does not exist", config_path);
+            (None, None)
+        };
 
         Runtime {
-            main_worker: main_worker_instance.worker,
-            event_sender: main_worker_instance.event_sender,
+            main_worker,
+            event_sender: Rc::new(RefCell::new(event_sender)),
             main_module,
+            script_dir,
+            extension_options,
+            inspector,
+            web_workers,
+            module_watcher,
+            reload_receiver,
             runtime_channel,
             runtime_sender,
             compositor_sender,
         }
     }
 
-    pub fn run_with_calloop(mut self, event_loop: &mut EventLoop<LoopData>) {
+    pub fn run_with_calloop(self, event_loop: &mut EventLoop<LoopData>) {
         let (exec, sched): (
             Executor<Result<(), AnyError>>,
             Scheduler<Result<(), AnyError>>,
@@ -67,35 +162,203 @@ impl Runtime {
         event_loop
             .handle()
             .insert_source(exec, |evt, _metadata, _shared| {
-                evt.unwrap();
+                if let Err(err) = evt {
+                    slog_scope::error!("Runtime stopped: {}", err);
+                }
             })
             .unwrap();
 
         let compositor_sender = self.compositor_sender.clone();
+        let event_sender = self.event_sender.clone();
 
         event_loop
             .handle()
             .insert_source(
                 self.runtime_channel,
-                move |message, _metadata, _shared| match message {
+                move |message, _metadata, data| match message {
                     Event::Msg(RuntimeMessage::Ping) => {
                         slog_scope::info!("The runtime got a ping!");
                         compositor_sender.send(CompositorMessage::Ping).unwrap();
                     }
-                    Event::Msg(_) => todo!(),
+                    Event::Msg(message) => handle_message(message, data, &event_sender),
                     Event::Closed => todo!(),
                 },
             )
             .unwrap();
 
+        let Runtime {
+            mut main_worker,
+            main_module,
+            script_dir,
+            extension_options,
+            inspector,
+            web_workers,
+            module_watcher,
+            mut reload_receiver,
+            compositor_sender,
+            event_sender,
+            ..
+        } = self;
+
         sched
             .schedule(async move {
-                self.main_worker
-                    .execute_main_module(&self.main_module)
-                    .await
-                    .unwrap();
-                self.main_worker.run_event_loop(false).await
+                let mut crashes = 0;
+                loop {
+                    let mut reloaded = false;
+                    if let Some(main_worker) = main_worker.as_mut() {
+                        let started = Instant::now();
+                        let run = async {
+                            main_worker.execute_main_module(&main_module).await?;
+                            main_worker.run_event_loop(false).await
+                        };
+                        pin_mut!(run);
+
+                        match select(run, reload_receiver.next()).await {
+                            Either::Left((Ok(()), _)) => {
+                                slog_scope::info!("{} exited", main_module);
+                            }
+                            Either::Left((Err(err), _)) => {
+                                if started.elapsed() >= HEALTHY_RUN {
+                                    crashes = 0;
+                                }
+                                crashes += 1;
+                                slog_scope::error!(
+                                    "{} crashed ({}/{}): {}",
+                                    main_module,
+                                    crashes,
+                                    MAX_CRASHES,
+                                    errors::format_error(&err)
+                                );
+                                errors::notify(&extension_options.socket, &compositor_sender, &err);
+                            }
+                            Either::Right(_) => reloaded = true,
+                        }
+                    }
+
+                    if !reloaded {
+                        main_worker = None;
+                        *event_sender.borrow_mut() = None;
+                        compositor_sender
+                            .send(CompositorMessage::ScriptStopped)
+                            .unwrap();
+
+                        // Crashed scripts are restarted straight away, until they keep crashing
+                        if crashes == 0 || crashes >= MAX_CRASHES {
+                            if crashes >= MAX_CRASHES {
+                                slog_scope::warn!(
+                                    "{} keeps crashing, waiting for it to change",
+                                    main_module
+                                );
+                            }
+                            if reload_receiver.next().await.is_none() {
+                                return Ok(());
+                            }
+                            reloaded = true;
+                        }
+                    }
+                    if reloaded {
+                        crashes = 0;
+                    }
+                    // Editors tend to emit several events for a single save
+                    while let Ok(Some(())) = reload_receiver.try_next() {}
+
+                    if !main_module
+                        .to_file_path()
+                        .map(|path| path.exists())
+                        .unwrap_or(false)
+                    {
+                        main_worker = None;
+                        continue;
+                    }
+
+                    slog_scope::info!("Starting {}", main_module);
+                    let permissions =
+                        ScriptPermissions::load(&script_dir).to_permissions(&script_dir);
+                    let main_worker_instance = main::new(
+                        main_module.clone(),
+                        Rc::new(TypescriptModuleLoader::new(
+                            module_watcher.clone(),
+                            &script_dir,
+                            permissions.clone(),
+                        )),
+                        permissions,
+                        extension_options.clone(),
+                        inspector.clone(),
+                        web_workers.clone(),
+                    );
+                    main_worker = Some(main_worker_instance.worker);
+                    *event_sender.borrow_mut() = Some(main_worker_instance.event_sender);
+                    compositor_sender.send(CompositorMessage::Reloaded).unwrap();
+                }
             })
             .unwrap();
     }
 }
+
+/// Forwards a message to the script, or to the built-in policy when no script is running
+fn handle_message(message: RuntimeMessage, data: &mut LoopData, event_sender: &EventSender) {
+    if data.state.common.shell.fallback_active {
+        let dh = data.display.handle();
+        data.state.default_request(&dh, &message);
+        return;
+    }
+
+    let event = match message {
+        RuntimeMessage::Ping => extension::Event::Ping,
+        RuntimeMessage::WindowMapped { window } => extension::Event::WindowMapped {
+            id: window.id(),
+            app_id: window.app_id(),
+            title: window.title(),
+        },
+        RuntimeMessage::BeforeMap { window } => extension::Event::BeforeMap {
+            id: window.id(),
+            app_id: window.app_id(),
+            title: window.title(),
+        },
+        RuntimeMessage::OutputAdded { output } => {
+            let geometry = output.geometry();
+            extension::Event::OutputAdded {
+                name: output.name(),
+                x: geometry.loc.x,
+                y: geometry.loc.y,
+                width: geometry.size.w,
+                height: geometry.size.h,
+            }
+        }
+        RuntimeMessage::WindowUrgent { window } => {
+            extension::Event::WindowUrgent { id: window.id() }
+        }
+        RuntimeMessage::MoveRequest { window, .. } => {
+            extension::Event::MoveRequest { id: window.id() }
+        }
+        RuntimeMessage::ResizeRequest { window, edges, .. } => extension::Event::ResizeRequest {
+            id: window.id(),
+            edges: edges.into(),
+        },
+        RuntimeMessage::MaximizeRequest { window, output } => extension::Event::MaximizeRequest {
+            id: window.id(),
+            output: output.name(),
+        },
+        RuntimeMessage::UnmaximizeRequest { window } => {
+            extension::Event::UnmaximizeRequest { id: window.id() }
+        }
+        RuntimeMessage::UnfullscreenRequest { window } => {
+            extension::Event::UnfullscreenRequest { id: window.id() }
+        }
+        RuntimeMessage::Binding { id, state } => extension::Event::Binding { id, state },
+        RuntimeMessage::Gesture { id, update } => extension::Event::Gesture { id, update },
+        RuntimeMessage::OutputFrame { output, time } => extension::Event::OutputFrame {
+            output: output.name(),
+            time,
+        },
+        RuntimeMessage::Idle { idle } => extension::Event::Idle { idle },
+        RuntimeMessage::ShortcutsInhibited { inhibited } => {
+            extension::Event::ShortcutsInhibited { inhibited }
+        }
+    };
+
+    // The receiver is gone while a script is being torn down, the new script is caught up on boot
+    if let Some(sender) = event_sender.borrow().as_ref() {
+        let _ = sender.unbounded_send(event);
+    }
+}
@@ -1,3 +1,4 @@
+use deno_ast::EmitOptions;
 use deno_ast::MediaType;
 use deno_ast::ParseParams;
 use deno_ast::SourceTextInfo;
@@ -12,13 +13,212 @@ use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Error;
 
+use data_url::DataUrl;
+use deno_runtime::permissions::Permissions;
 use futures::FutureExt;
+use import_map::ImportMap;
 
+use std::cell::RefCell;
+use std::path::Path;
 use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::remote::RemoteModules;
+use super::source_maps::SourceMaps;
+use super::stdlib;
+use super::transpile_cache::TranspileCache;
+use super::watcher::ModuleWatcher;
+
+pub const IMPORT_MAP_FILE: &str = "import_map.json";
 
 // https://github.com/denoland/deno/blob/main/core/examples/ts_module_loader.rs
 
-pub struct TypescriptModuleLoader;
+pub struct TypescriptModuleLoader {
+    watcher: Arc<ModuleWatcher>,
+    remote: Rc<RemoteModules>,
+    /// Those of the worker loading the modules, checked for dynamic imports
+    permissions: RefCell<Permissions>,
+    import_map: Option<ImportMap>,
+    source_maps: SourceMaps,
+    transpile_cache: TranspileCache,
+}
+
+impl TypescriptModuleLoader {
+    pub fn new(watcher: Arc<ModuleWatcher>, script_dir: &Path, permissions: Permissions) -> Self {
+        TypescriptModuleLoader {
+            watcher,
+            remote: Rc::new(RemoteModules::new(script_dir)),
+            permissions: RefCell::new(permissions),
+            import_map: load_import_map(script_dir),
+            source_maps: SourceMaps::default(),
+            transpile_cache: TranspileCache::new(),
+        }
+    }
+
+    pub fn source_maps(&self) -> SourceMaps {
+        self.source_maps.clone()
+    }
+
+    fn resolve_specifier(&self, specifier: &str, referrer: &str) -> Result<ModuleSpecifier, Error> {
+        if let Some(import_map) = &self.import_map {
+            // The main module is resolved against "."
+            if let Ok(referrer) = deno_core::resolve_url_or_path(referrer) {
+                if let Ok(resolved) = import_map.resolve(specifier, &referrer) {
+                    return Ok(resolved);
+                }
+            }
+        }
+        if let Some(resolved) = stdlib::resolve(specifier) {
+            return Ok(resolved);
+        }
+
+        Ok(resolve_import(specifier, referrer)?)
+    }
+
+    /// Checks remote modules against `lock.json` without recording new ones
+    pub fn verify_lockfile_only(&self) {
+        self.remote.verify_lockfile_only();
+    }
+
+    /// A dynamic import needs permission to read the file or reach the host, as it does in Deno,
+    /// so `import()` cannot read or send out what the script may not
+    fn check_dynamic_import(&self, specifier: &ModuleSpecifier) -> Result<(), Error> {
+        if !matches!(specifier.scheme(), "file" | "http" | "https") {
+            return Ok(());
+        }
+        self.permissions
+            .borrow_mut()
+            .check_specifier(specifier)
+            .map_err(|err| {
+                slog_scope::warn!("Script was denied permission: {}", err);
+                err
+            })
+    }
+}
+
+fn is_remote(specifier: &str) -> bool {
+    specifier.starts_with("http://") || specifier.starts_with("https://")
+}
+
+/// Reads the `import_map.json` beside the script, if there is one
+fn load_import_map(script_dir: &Path) -> Option<ImportMap> {
+    let path = script_dir.join(IMPORT_MAP_FILE);
+    let contents = std::fs::read_to_string(&path).ok()?;
+    let base = ModuleSpecifier::from_file_path(&path).ok()?;
+
+    match import_map::parse_from_json(&base, &contents) {
+        Ok(result) => {
+            for diagnostic in result.diagnostics {
+                slog_scope::warn!("{:?}: {}", path, diagnostic);
+            }
+            Some(result.import_map)
+        }
+        Err(err) => {
+            slog_scope::error!("Failed to read {:?}, ignoring it: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Picks the media type of a module that is not a file from its content type,
+/// falling back to the extension in its URL
+fn media_type_for(specifier: &ModuleSpecifier, content_type: Option<&str>) -> MediaType {
+    let from_path = MediaType::from(Path::new(specifier.path()));
+    let mime = match content_type {
+        Some(content_type) => content_type
+            .split(';')
+            .next()
+            .unwrap()
+            .trim()
+            .to_lowercase(),
+        None => return from_path,
+    };
+
+    match mime.as_str() {
+        "application/typescript"
+        | "text/typescript"
+        | "application/x-typescript"
+        | "video/mp2t"
+        | "video/vnd.dlna.mpeg-tts" => match from_path {
+            MediaType::Tsx | MediaType::Dts | MediaType::Dmts | MediaType::Dcts => from_path,
+            _ => MediaType::TypeScript,
+        },
+        "application/javascript"
+        | "text/javascript"
+        | "application/ecmascript"
+        | "text/ecmascript"
+        | "application/x-javascript"
+        | "application/node" => match from_path {
+            MediaType::Jsx => from_path,
+            _ => MediaType::JavaScript,
+        },
+        "text/jsx" => MediaType::Jsx,
+        "text/tsx" => MediaType::Tsx,
+        "application/json" | "text/json" => MediaType::Json,
+        _ => from_path,
+    }
+}
+
+fn module_source(
+    source_maps: &SourceMaps,
+    transpile_cache: &TranspileCache,
+    specifier: &ModuleSpecifier,
+    found: &ModuleSpecifier,
+    media_type: MediaType,
+    code: String,
+) -> Result<ModuleSource, Error> {
+    let (module_type, should_transpile) = match media_type {
+        MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs => (ModuleType::JavaScript, false),
+        MediaType::Jsx => (ModuleType::JavaScript, true),
+        MediaType::TypeScript
+        | MediaType::Mts
+        | MediaType::Cts
+        | MediaType::Dts
+        | MediaType::Dmts
+        | MediaType::Dcts
+        | MediaType::Tsx => (ModuleType::JavaScript, true),
+        MediaType::Json => (ModuleType::Json, false),
+        _ => bail!("Unknown media type for {}", specifier),
+    };
+
+    let source = code;
+    let code = if should_transpile {
+        let key = TranspileCache::key(found.as_str(), media_type, &source);
+        match transpile_cache.get(&key) {
+            Some(code) => code,
+            None => {
+                let parsed = deno_ast::parse_module(ParseParams {
+                    specifier: found.to_string(),
+                    text_info: SourceTextInfo::from_string(source.clone()),
+                    media_type,
+                    capture_tokens: false,
+                    scope_analysis: false,
+                    maybe_syntax: None,
+                })?;
+                let code = parsed
+                    .transpile(&EmitOptions {
+                        inline_source_map: true,
+                        inline_sources: true,
+                        ..Default::default()
+                    })?
+                    .text;
+                transpile_cache.insert(&key, &code);
+                code
+            }
+        }
+    } else {
+        source.clone()
+    };
+    source_maps.insert(found.to_string(), code.clone(), source);
+
+    Ok(ModuleSource {
+        code: code.into_bytes().into_boxed_slice(),
+        module_type,
+        module_url_specified: specifier.to_string(),
+        module_url_found: found.to_string(),
+    })
+}
 
 impl ModuleLoader for TypescriptModuleLoader {
     fn resolve(
@@ -27,60 +227,179 @@ impl ModuleLoader for TypescriptModuleLoader {
         referrer: &str,
         _is_main: bool,
     ) -> Result<ModuleSpecifier, Error> {
-        Ok(resolve_import(specifier, referrer)?)
+        let resolved = self.resolve_specifier(specifier, referrer)?;
+        // Like in Deno, a module from the network may not reach into the local filesystem
+        if resolved.scheme() == "file" && is_remote(referrer) {
+            bail!(
+                "Remote module {} may not import the local module {}",
+                referrer,
+                resolved
+            );
+        }
+        Ok(resolved)
     }
 
     fn load(
         &self,
         module_specifier: &ModuleSpecifier,
         _maybe_referrer: Option<ModuleSpecifier>,
-        _is_dyn_import: bool,
+        is_dyn_import: bool,
     ) -> Pin<Box<ModuleSourceFuture>> {
+        let permitted = if is_dyn_import {
+            self.check_dynamic_import(module_specifier)
+        } else {
+            Ok(())
+        };
         let module_specifier = module_specifier.clone();
+        let watcher = self.watcher.clone();
+        let remote = self.remote.clone();
+        let source_maps = self.source_maps.clone();
+        let transpile_cache = self.transpile_cache.clone();
         async move {
-            let path = module_specifier
-                .to_file_path()
-                .map_err(|_| anyhow!("Only file: URLs are supported."))?;
-
-            let media_type = MediaType::from(&path);
-            let (module_type, should_transpile) = match MediaType::from(&path) {
-                MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs => {
-                    (ModuleType::JavaScript, false)
+            permitted?;
+            match module_specifier.scheme() {
+                "file" => {
+                    let path = module_specifier
+                        .to_file_path()
+                        .map_err(|_| anyhow!("Invalid file URL {}", module_specifier))?;
+
+                    // Watch before reading, so a module that fails to transpile is still reloaded once fixed
+                    watcher.watch(&path);
+
+                    let code = std::fs::read_to_string(&path)?;
+                    module_source(
+                        &source_maps,
+                        &transpile_cache,
+                        &module_specifier,
+                        &module_specifier,
+                        MediaType::from(&path),
+                        code,
+                    )
                 }
-                MediaType::Jsx => (ModuleType::JavaScript, true),
-                MediaType::TypeScript
-                | MediaType::Mts
-                | MediaType::Cts
-                | MediaType::Dts
-                | MediaType::Dmts
-                | MediaType::Dcts
-                | MediaType::Tsx => (ModuleType::JavaScript, true),
-                MediaType::Json => (ModuleType::Json, false),
-                _ => bail!("Unknown extension {:?}", path.extension()),
-            };
-
-            let code = std::fs::read_to_string(&path)?;
-            let code = if should_transpile {
-                let parsed = deno_ast::parse_module(ParseParams {
-                    specifier: module_specifier.to_string(),
-                    text_info: SourceTextInfo::from_string(code),
-                    media_type,
-                    capture_tokens: false,
-                    scope_analysis: false,
-                    maybe_syntax: None,
-                })?;
-                parsed.transpile(&Default::default())?.text
-            } else {
-                code
-            };
-            let module = ModuleSource {
-                code: code.into_bytes().into_boxed_slice(),
-                module_type,
-                module_url_specified: module_specifier.to_string(),
-                module_url_found: module_specifier.to_string(),
-            };
-            Ok(module)
+                stdlib::SCHEME => {
+                    let (media_type, code) = stdlib::get(&module_specifier).ok_or_else(|| {
+                        anyhow!("There is no built-in module {}", module_specifier)
+                    })?;
+                    module_source(
+                        &source_maps,
+                        &transpile_cache,
+                        &module_specifier,
+                        &module_specifier,
+                        media_type,
+                        code.to_string(),
+                    )
+                }
+                "data" => {
+                    let url = DataUrl::process(module_specifier.as_str())
+                        .map_err(|err| anyhow!("Invalid data URL: {:?}", err))?;
+                    let mime = url.mime_type();
+                    let content_type = format!("{}/{}", mime.type_, mime.subtype);
+                    let (code, _) = url
+                        .decode_to_vec()
+                        .map_err(|err| anyhow!("Invalid data URL: {:?}", err))?;
+                    module_source(
+                        &source_maps,
+                        &transpile_cache,
+                        &module_specifier,
+                        &module_specifier,
+                        media_type_for(&module_specifier, Some(&content_type)),
+                        String::from_utf8(code)?,
+                    )
+                }
+                "http" | "https" => {
+                    let module = remote.load(&module_specifier).await?;
+                    module_source(
+                        &source_maps,
+                        &transpile_cache,
+                        &module_specifier,
+                        &module.url,
+                        media_type_for(&module.url, module.content_type.as_deref()),
+                        String::from_utf8(module.code)?,
+                    )
+                }
+                scheme => bail!("Unsupported scheme {:?} in {}", scheme, module_specifier),
+            }
         }
         .boxed_local()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::super::permissions::ScriptPermissions;
+    use super::*;
+
+    fn loader(script_dir: &Path) -> TypescriptModuleLoader {
+        let (watcher, _) = ModuleWatcher::new().unwrap();
+        TypescriptModuleLoader::new(
+            Arc::new(watcher),
+            script_dir,
+            ScriptPermissions::default().to_permissions(script_dir),
+        )
+    }
+
+    fn load(
+        loader: &TypescriptModuleLoader,
+        specifier: &ModuleSpecifier,
+        referrer: &ModuleSpecifier,
+        is_dyn_import: bool,
+    ) -> Result<ModuleSource, Error> {
+        block_on(loader.load(specifier, Some(referrer.clone()), is_dyn_import))
+    }
+
+    #[test]
+    fn dynamic_imports_outside_the_script_dir_are_refused() {
+        let script_dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let module = outside.path().join("secret.js");
+        std::fs::write(&module, "export default 1;\n").unwrap();
+
+        let loader = loader(script_dir.path());
+        let referrer = ModuleSpecifier::from_file_path(script_dir.path().join("main.ts")).unwrap();
+        let specifier = ModuleSpecifier::from_file_path(&module).unwrap();
+        let err = load(&loader, &specifier, &referrer, true).err().unwrap();
+        assert_eq!(
+            deno_runtime::errors::get_error_class_name(&err),
+            Some("PermissionDenied")
+        );
+    }
+
+    #[test]
+    fn dynamic_imports_need_net_permission() {
+        let script_dir = tempfile::tempdir().unwrap();
+        let loader = loader(script_dir.path());
+        let referrer = ModuleSpecifier::from_file_path(script_dir.path().join("main.ts")).unwrap();
+        let specifier = ModuleSpecifier::parse("https://example.com/?secret").unwrap();
+        let err = load(&loader, &specifier, &referrer, true).err().unwrap();
+        assert_eq!(
+            deno_runtime::errors::get_error_class_name(&err),
+            Some("PermissionDenied")
+        );
+    }
+
+    #[test]
+    fn remote_modules_cannot_import_files() {
+        let script_dir = tempfile::tempdir().unwrap();
+        let loader = loader(script_dir.path());
+        assert!(loader
+            .resolve("file:///etc/passwd", "https://example.com/mod.ts", false)
+            .is_err());
+        assert!(loader
+            .resolve("./other.ts", "https://example.com/mod.ts", false)
+            .is_ok());
+    }
+
+    #[test]
+    fn dynamic_imports_inside_the_script_dir_are_loaded() {
+        let script_dir = tempfile::tempdir().unwrap();
+        let module = script_dir.path().join("module.js");
+        std::fs::write(&module, "export default 1;\n").unwrap();
+
+        let loader = loader(script_dir.path());
+        let referrer = ModuleSpecifier::from_file_path(script_dir.path().join("main.ts")).unwrap();
+        let specifier = ModuleSpecifier::from_file_path(&module).unwrap();
+        assert!(load(&loader, &specifier, &referrer, true).is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use deno_core::serde::Deserialize;
+use deno_core::serde_json;
+use deno_runtime::permissions::{Permissions, PermissionsOptions};
+
+pub const PERMISSIONS_FILE: &str = "permissions.json";
+
+/// Extra permissions granted to a script, read from `permissions.json` beside it.
+///
+/// Without this file a script may only read its own directory.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ScriptPermissions {
+    pub read: Vec<PathBuf>,
+    pub write: Vec<PathBuf>,
+    pub run: Vec<String>,
+    pub net: Vec<String>,
+    pub env: Vec<String>,
+}
+
+impl ScriptPermissions {
+    pub fn load(script_dir: &Path) -> Self {
+        let path = script_dir.join(PERMISSIONS_FILE);
+        if !path.exists() {
+            return ScriptPermissions::default();
+        }
+
+        match std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+        {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                slog_scope::error!("Failed to read {:?}, using defaults: {}", path, err);
+                ScriptPermissions::default()
+            }
+        }
+    }
+
+    pub fn to_permissions(&self, script_dir: &Path) -> Permissions {
+        let mut read = vec![script_dir.to_path_buf()];
+        read.extend(self.read.iter().cloned());
+
+        Permissions::from_options(&PermissionsOptions {
+            allow_env: allow_list(&self.env),
+            allow_hrtime: false,
+            allow_net: allow_list(&self.net),
+            allow_ffi: None,
+            allow_read: allow_list(&read),
+            allow_run: allow_list(&self.run),
+            allow_write: allow_list(&self.write),
+            prompt: false,
+        })
+    }
+}
+
+/// Deno treats an empty list as "allow everything", so it has to become `None`
+fn allow_list<T: Clone>(list: &[T]) -> Option<Vec<T>> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(list.to_vec())
+    }
+}
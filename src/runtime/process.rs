@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use deno_core::serde::Deserialize;
+use futures::channel::mpsc::UnboundedSender;
+use smithay::reexports::nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+
+use super::extension::Event;
+
+/// A restarted process that exits faster than this counts towards giving up on it
+const MIN_UPTIME: Duration = Duration::from_secs(1);
+/// Fast consecutive exits after which a supervised process is no longer restarted
+const MAX_FAST_EXITS: u32 = 5;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpawnOptions {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Restart the process whenever it exits unsuccessfully, for panels, wallpapers and the like
+    #[serde(default)]
+    pub restart: bool,
+}
+
+struct Supervised {
+    pid: u32,
+    restart: bool,
+    stopped: bool,
+}
+
+/// Processes started by a script, each identified by a handle that survives restarts
+pub struct Processes {
+    socket: OsString,
+    /// Where exits are reported. Web workers have no events, so they cannot supervise processes
+    event_sender: Option<UnboundedSender<Event>>,
+    next_handle: u32,
+    children: Arc<Mutex<HashMap<u32, Supervised>>>,
+}
+
+impl Processes {
+    pub fn new(socket: OsString, event_sender: Option<UnboundedSender<Event>>) -> Self {
+        Processes {
+            socket,
+            event_sender,
+            next_handle: 1,
+            children: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn spawn(&mut self, options: SpawnOptions) -> io::Result<u32> {
+        if options.restart && self.event_sender.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only the main script can restart processes",
+            ));
+        }
+
+        let mut command = Command::new(&options.command);
+        command
+            .args(&options.args)
+            .envs(&options.env)
+            .env("WAYLAND_DISPLAY", &self.socket);
+        let child = command.spawn()?;
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.children.lock().unwrap().insert(
+            handle,
+            Supervised {
+                pid: child.id(),
+                restart: options.restart,
+                stopped: false,
+            },
+        );
+
+        let children = self.children.clone();
+        let event_sender = self.event_sender.clone();
+        thread::Builder::new()
+            .name(format!("electrum-process-{}", handle))
+            .spawn(move || supervise(handle, child, command, children, event_sender))?;
+
+        Ok(handle)
+    }
+
+    pub fn kill(&self, handle: u32) {
+        if let Some(process) = self.children.lock().unwrap().get_mut(&handle) {
+            process.stopped = true;
+            terminate(process.pid);
+        }
+    }
+}
+
+// Supervised services belong to the script and go away with it, so a reloaded script
+// does not start a second copy. Anything else, like a terminal, is left running.
+impl Drop for Processes {
+    fn drop(&mut self) {
+        for process in self.children.lock().unwrap().values_mut() {
+            if process.restart {
+                process.stopped = true;
+                terminate(process.pid);
+            }
+        }
+    }
+}
+
+fn terminate(pid: u32) {
+    if let Err(err) = kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
+        slog_scope::warn!("Failed to terminate process {}: {}", pid, err);
+    }
+}
+
+fn supervise(
+    handle: u32,
+    mut child: Child,
+    mut command: Command,
+    children: Arc<Mutex<HashMap<u32, Supervised>>>,
+    event_sender: Option<UnboundedSender<Event>>,
+) {
+    let mut fast_exits = 0;
+    loop {
+        let started = Instant::now();
+        let status = match child.wait() {
+            Ok(status) => status,
+            Err(err) => {
+                slog_scope::error!("Failed to wait on process {}: {}", child.id(), err);
+                break;
+            }
+        };
+
+        let restarting = {
+            let children = children.lock().unwrap();
+            let process = children.get(&handle).unwrap();
+            process.restart && !process.stopped && !status.success()
+        };
+        if let Some(event_sender) = &event_sender {
+            let _ = event_sender.unbounded_send(Event::ProcessExited {
+                handle,
+                code: status.code(),
+                signal: status.signal(),
+                restarting,
+            });
+        }
+        if !restarting {
+            break;
+        }
+
+        if started.elapsed() < MIN_UPTIME {
+            fast_exits += 1;
+            if fast_exits >= MAX_FAST_EXITS {
+                slog_scope::warn!("{:?} keeps exiting, no longer restarting it", command);
+                break;
+            }
+            thread::sleep(MIN_UPTIME);
+        } else {
+            fast_exits = 0;
+        }
+        if children.lock().unwrap().get(&handle).unwrap().stopped {
+            break;
+        }
+
+        child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                slog_scope::error!("Failed to restart {:?}: {}", command, err);
+                break;
+            }
+        };
+        children.lock().unwrap().get_mut(&handle).unwrap().pid = child.id();
+    }
+
+    children.lock().unwrap().remove(&handle);
+}
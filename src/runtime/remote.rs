@@ -0,0 +1,340 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Error};
+use deno_core::serde::{Deserialize, Serialize};
+use deno_core::serde_json;
+use deno_core::ModuleSpecifier;
+use futures::channel::oneshot;
+use sha2::{Digest, Sha256};
+
+pub const LOCK_FILE: &str = "lock.json";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A module fetched over http(s), either from the network or from the cache
+pub struct RemoteModule {
+    /// Where the module ended up after redirects, relative imports resolve against this
+    pub url: ModuleSpecifier,
+    pub content_type: Option<String>,
+    pub code: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    url: String,
+    content_type: Option<String>,
+}
+
+/// Hashes of every remote module the script has loaded, kept in `lock.json` beside it
+struct Lockfile {
+    path: PathBuf,
+    entries: BTreeMap<String, String>,
+    /// Only verifies modules, new ones are not recorded
+    read_only: bool,
+}
+
+impl Lockfile {
+    fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                slog_scope::error!("Failed to parse {:?}, ignoring it: {}", path, err);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Lockfile {
+            path,
+            entries,
+            read_only: false,
+        }
+    }
+
+    /// Checks a module against its recorded hash, recording it if it is new
+    fn check(&mut self, specifier: &ModuleSpecifier, code: &[u8]) -> Result<(), Error> {
+        let hash = format!("{:x}", Sha256::digest(code));
+        match self.entries.get(specifier.as_str()) {
+            Some(expected) if *expected == hash => Ok(()),
+            Some(expected) => bail!(
+                "Integrity check failed for {}: {} expects {} but got {}. \
+                 Remove its entry if the change is expected",
+                specifier,
+                LOCK_FILE,
+                expected,
+                hash
+            ),
+            None if self.read_only => Ok(()),
+            None => {
+                self.entries.insert(specifier.to_string(), hash);
+                if let Err(err) = serde_json::to_string_pretty(&self.entries)
+                    .map_err(Error::from)
+                    .and_then(|contents| Ok(std::fs::write(&self.path, contents + "\n")?))
+                {
+                    slog_scope::error!("Failed to write {:?}: {}", self.path, err);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fetches http(s) modules, caching them under the XDG cache directory.
+///
+/// With `ELECTRUM_OFFLINE` set only cached modules are used.
+pub struct RemoteModules {
+    cache_dir: PathBuf,
+    lockfile: RefCell<Lockfile>,
+    offline: bool,
+}
+
+impl RemoteModules {
+    pub fn new(script_dir: &Path) -> Self {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("electrum").unwrap();
+        RemoteModules::with_cache(
+            xdg_dirs.get_cache_home().join("deps"),
+            script_dir,
+            env::var_os("ELECTRUM_OFFLINE").is_some(),
+        )
+    }
+
+    fn with_cache(cache_dir: PathBuf, script_dir: &Path, offline: bool) -> Self {
+        RemoteModules {
+            cache_dir,
+            lockfile: RefCell::new(Lockfile::load(script_dir.join(LOCK_FILE))),
+            offline,
+        }
+    }
+
+    /// Checks modules against `lock.json` without ever writing it, for `electrum check`
+    pub fn verify_lockfile_only(&self) {
+        self.lockfile.borrow_mut().read_only = true;
+    }
+
+    pub async fn load(&self, specifier: &ModuleSpecifier) -> Result<RemoteModule, Error> {
+        let module = match self.read_cache(specifier) {
+            Some(module) => module,
+            None if self.offline => {
+                bail!("{} is not cached and ELECTRUM_OFFLINE is set", specifier)
+            }
+            None => {
+                slog_scope::info!("Downloading {}", specifier);
+                let module = fetch(specifier.clone()).await?;
+                self.lockfile.borrow_mut().check(specifier, &module.code)?;
+                if let Err(err) = self.write_cache(specifier, &module) {
+                    slog_scope::warn!("Failed to cache {}: {}", specifier, err);
+                }
+                return Ok(module);
+            }
+        };
+
+        self.lockfile.borrow_mut().check(specifier, &module.code)?;
+        Ok(module)
+    }
+
+    /// `<cache>/deps/<scheme>/<host>/<hash of the url>`, like Deno's own cache
+    fn cache_path(&self, specifier: &ModuleSpecifier) -> PathBuf {
+        let mut host = specifier.host_str().unwrap_or("").to_string();
+        if let Some(port) = specifier.port() {
+            host = format!("{}_PORT{}", host, port);
+        }
+        self.cache_dir
+            .join(specifier.scheme())
+            .join(host)
+            .join(format!("{:x}", Sha256::digest(specifier.as_str())))
+    }
+
+    fn read_cache(&self, specifier: &ModuleSpecifier) -> Option<RemoteModule> {
+        let path = self.cache_path(specifier);
+        let metadata: Metadata =
+            serde_json::from_slice(&std::fs::read(path.with_extension("metadata.json")).ok()?)
+                .ok()?;
+        Some(RemoteModule {
+            url: ModuleSpecifier::parse(&metadata.url).ok()?,
+            content_type: metadata.content_type,
+            code: std::fs::read(&path).ok()?,
+        })
+    }
+
+    fn write_cache(&self, specifier: &ModuleSpecifier, module: &RemoteModule) -> Result<(), Error> {
+        let path = self.cache_path(specifier);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, &module.code)?;
+        // Written last, so an interrupted write is never mistaken for a cached module
+        std::fs::write(
+            path.with_extension("metadata.json"),
+            serde_json::to_vec(&Metadata {
+                url: module.url.to_string(),
+                content_type: module.content_type.clone(),
+            })?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Downloads on a separate thread, so a slow server does not stall the compositor
+async fn fetch(specifier: ModuleSpecifier) -> Result<RemoteModule, Error> {
+    let (sender, receiver) = oneshot::channel();
+    thread::Builder::new()
+        .name("electrum-fetch".to_string())
+        .spawn(move || {
+            let result = (|| {
+                let response = ureq::AgentBuilder::new()
+                    .timeout(FETCH_TIMEOUT)
+                    .build()
+                    .get(specifier.as_str())
+                    .call()?;
+                let url = ModuleSpecifier::parse(response.get_url())?;
+                let content_type = response.header("content-type").map(str::to_string);
+                let mut code = Vec::new();
+                response.into_reader().read_to_end(&mut code)?;
+                Ok(RemoteModule {
+                    url,
+                    content_type,
+                    code,
+                })
+            })()
+            .map_err(|err: Error| anyhow!("Failed to download {}: {}", specifier, err));
+            let _ = sender.send(result);
+        })?;
+
+    receiver.await?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    const MODULE: &[u8] = b"export const answer = 42;\n";
+
+    /// Answers a single request with `MODULE`
+    fn serve_once() -> ModuleSpecifier {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..read]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/typescript\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                MODULE.len()
+            )
+            .unwrap();
+            stream.write_all(MODULE).unwrap();
+        });
+        ModuleSpecifier::parse(&format!("http://127.0.0.1:{}/mod.ts", port)).unwrap()
+    }
+
+    fn lockfile(dir: &Path) -> Lockfile {
+        Lockfile::load(dir.join(LOCK_FILE))
+    }
+
+    #[test]
+    fn lockfile_records_new_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let specifier = ModuleSpecifier::parse("https://example.com/mod.ts").unwrap();
+        lockfile(dir.path()).check(&specifier, MODULE).unwrap();
+
+        let entries = lockfile(dir.path()).entries;
+        assert_eq!(
+            entries.get(specifier.as_str()),
+            Some(&format!("{:x}", Sha256::digest(MODULE)))
+        );
+    }
+
+    #[test]
+    fn lockfile_accepts_matching_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let specifier = ModuleSpecifier::parse("https://example.com/mod.ts").unwrap();
+        lockfile(dir.path()).check(&specifier, MODULE).unwrap();
+        assert!(lockfile(dir.path()).check(&specifier, MODULE).is_ok());
+    }
+
+    #[test]
+    fn lockfile_rejects_changed_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let specifier = ModuleSpecifier::parse("https://example.com/mod.ts").unwrap();
+        lockfile(dir.path()).check(&specifier, MODULE).unwrap();
+
+        let mut lockfile = lockfile(dir.path());
+        let err = lockfile.check(&specifier, b"export {};\n").unwrap_err();
+        assert!(err.to_string().contains("Integrity check failed"));
+        // The recorded hash is kept
+        assert_eq!(
+            lockfile.entries.get(specifier.as_str()),
+            Some(&format!("{:x}", Sha256::digest(MODULE)))
+        );
+    }
+
+    #[test]
+    fn read_only_lockfile_records_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let specifier = ModuleSpecifier::parse("https://example.com/mod.ts").unwrap();
+        let mut lockfile = lockfile(dir.path());
+        lockfile.read_only = true;
+        lockfile.check(&specifier, MODULE).unwrap();
+        assert!(lockfile.entries.is_empty());
+        assert!(!dir.path().join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn fetches_and_caches_modules() {
+        let cache = tempfile::tempdir().unwrap();
+        let script = tempfile::tempdir().unwrap();
+        let specifier = serve_once();
+
+        let remote = RemoteModules::with_cache(cache.path().to_path_buf(), script.path(), false);
+        let module = block_on(remote.load(&specifier)).unwrap();
+        assert_eq!(module.url, specifier);
+        assert_eq!(
+            module.content_type.as_deref(),
+            Some("application/typescript")
+        );
+        assert_eq!(module.code, MODULE);
+        assert!(lockfile(script.path())
+            .entries
+            .contains_key(specifier.as_str()));
+
+        // The server is gone, so this can only come from the cache
+        let offline = RemoteModules::with_cache(cache.path().to_path_buf(), script.path(), true);
+        let cached = block_on(offline.load(&specifier)).unwrap();
+        assert_eq!(cached.url, specifier);
+        assert_eq!(cached.code, MODULE);
+    }
+
+    #[test]
+    fn offline_refuses_uncached_modules() {
+        let cache = tempfile::tempdir().unwrap();
+        let script = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let specifier = ModuleSpecifier::parse(&format!(
+            "http://127.0.0.1:{}/mod.ts",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+
+        let remote = RemoteModules::with_cache(cache.path().to_path_buf(), script.path(), true);
+        let err = block_on(remote.load(&specifier)).err().unwrap();
+        assert!(err.to_string().contains("ELECTRUM_OFFLINE"));
+        // Nothing was requested
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use data_url::DataUrl;
+use deno_core::SourceMapGetter;
+
+const SOURCE_MAP_PREFIX: &str = "//# sourceMappingURL=data:";
+
+struct LoadedModule {
+    /// What V8 runs, ending in an inline source map if it was transpiled
+    code: String,
+    /// What the script author wrote
+    source: String,
+}
+
+/// The modules loaded by a worker, so errors can be mapped back to their TypeScript sources
+#[derive(Clone, Default)]
+pub struct SourceMaps(Rc<RefCell<HashMap<String, LoadedModule>>>);
+
+impl SourceMaps {
+    pub fn insert(&self, specifier: String, code: String, source: String) {
+        self.0
+            .borrow_mut()
+            .insert(specifier, LoadedModule { code, source });
+    }
+}
+
+impl SourceMapGetter for SourceMaps {
+    fn get_source_map(&self, file_name: &str) -> Option<Vec<u8>> {
+        let modules = self.0.borrow();
+        let code = &modules.get(file_name)?.code;
+        let url = code.lines().rev().find_map(|line| {
+            line.strip_prefix(SOURCE_MAP_PREFIX)
+                .map(|rest| format!("data:{}", rest))
+        })?;
+        let (source_map, _) = DataUrl::process(&url).ok()?.decode_to_vec().ok()?;
+        Some(source_map)
+    }
+
+    fn get_source_line(&self, file_name: &str, line_number: usize) -> Option<String> {
+        let modules = self.0.borrow();
+        modules
+            .get(file_name)?
+            .source
+            .lines()
+            .nth(line_number)
+            .map(str::to_string)
+    }
+}
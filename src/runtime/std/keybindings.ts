@@ -0,0 +1,36 @@
+// Helpers for registering groups of key bindings.
+
+import type { Binding, BindingState, KeyBindingOptions } from "electrum:types";
+
+export type KeyMap = Record<string, () => void>;
+
+/** Binds every combo in `keys` to its callback, returning a binding that removes them all */
+export function bindKeys(keys: KeyMap, options: KeyBindingOptions = {}): Binding {
+  const bindings = Object.entries(keys).map(([combo, callback]) =>
+    electrum.bindKey(combo, (state: BindingState) => {
+      if (state !== "Released") callback();
+    }, options)
+  );
+  return {
+    id: bindings[0]?.id ?? 0,
+    unbind: () => bindings.forEach((binding) => binding.unbind()),
+  };
+}
+
+/** A named mode, such as a resize mode, entered with `enter` and left with one of `exit` */
+export function defineMode(
+  mode: string,
+  keys: KeyMap,
+  exit: string[] = ["Escape", "Return"],
+): Binding {
+  const exits = Object.fromEntries(exit.map((combo) => [combo, () => leaveMode()]));
+  return bindKeys({ ...keys, ...exits }, { mode });
+}
+
+export function enterMode(mode: string) {
+  electrum.setBindingMode(mode);
+}
+
+export function leaveMode() {
+  electrum.setBindingMode("default");
+}
@@ -0,0 +1,102 @@
+// Geometry helpers for tiling layouts. Each layout splits an area into one
+// rectangle per window, in the order the windows are given.
+
+export interface Rect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface LayoutOptions {
+  /** Space between windows and around the edges of the area */
+  gap?: number;
+}
+
+export interface MasterStackOptions extends LayoutOptions {
+  /** Fraction of the width given to the master windows */
+  ratio?: number;
+  /** Number of windows in the master column */
+  masters?: number;
+}
+
+function shrink(area: Rect, by: number): Rect {
+  return {
+    x: area.x + by,
+    y: area.y + by,
+    width: Math.max(area.width - by * 2, 0),
+    height: Math.max(area.height - by * 2, 0),
+  };
+}
+
+/**
+ * Stacks `count` rectangles vertically with `gap` between them, the last one taking up any
+ * rounding remainder
+ */
+function split(area: Rect, count: number, gap: number): Rect[] {
+  if (count <= 0) return [];
+
+  const height = Math.floor((area.height - gap * (count - 1)) / count);
+  return Array.from({ length: count }, (_, i) => {
+    const y = area.y + i * (height + gap);
+    return {
+      x: area.x,
+      y,
+      width: area.width,
+      height: i === count - 1 ? area.y + area.height - y : height,
+    };
+  });
+}
+
+function transpose(rect: Rect): Rect {
+  return { x: rect.y, y: rect.x, width: rect.height, height: rect.width };
+}
+
+/** Splits an area into `count` rows */
+export function rows(area: Rect, count: number, options: LayoutOptions = {}): Rect[] {
+  const gap = options.gap ?? 0;
+  return split(shrink(area, gap), count, gap);
+}
+
+/** Splits an area into `count` columns */
+export function columns(area: Rect, count: number, options: LayoutOptions = {}): Rect[] {
+  return rows(transpose(area), count, options).map(transpose);
+}
+
+/** Every window takes up the whole area */
+export function monocle(area: Rect, count: number, options: LayoutOptions = {}): Rect[] {
+  const inner = shrink(area, options.gap ?? 0);
+  return Array.from({ length: count }, () => ({ ...inner }));
+}
+
+/** The nearest to square grid that fits every window */
+export function grid(area: Rect, count: number, options: LayoutOptions = {}): Rect[] {
+  const gap = options.gap ?? 0;
+  const cols = Math.ceil(Math.sqrt(count));
+  const rowCount = Math.ceil(count / cols);
+  return rows(area, rowCount, options).flatMap((row, i) =>
+    split(transpose(row), Math.min(cols, count - i * cols), gap).map(transpose)
+  );
+}
+
+/** Master windows stacked on the left, everything else stacked on the right */
+export function masterStack(
+  area: Rect,
+  count: number,
+  options: MasterStackOptions = {},
+): Rect[] {
+  const gap = options.gap ?? 0;
+  const masters = Math.max(options.masters ?? 1, 1);
+  if (count <= masters) return rows(area, count, options);
+
+  const inner = shrink(area, gap);
+  const masterWidth = Math.floor((inner.width - gap) * (options.ratio ?? 0.5));
+  return [
+    ...split({ ...inner, width: masterWidth }, masters, gap),
+    ...split(
+      { ...inner, x: inner.x + masterWidth + gap, width: inner.width - masterWidth - gap },
+      count - masters,
+      gap,
+    ),
+  ];
+}
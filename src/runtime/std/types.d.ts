@@ -0,0 +1,145 @@
+// Type definitions for the `electrum` global, for editors:
+// /// <reference types="electrum:types" />
+
+export type BindingState = "Pressed" | "Released" | "Repeated";
+
+export type GestureUpdate =
+  | "Begin"
+  | { Update: { dx: number; dy: number; scale: number; rotation: number } }
+  | { End: { cancelled: boolean } };
+
+export type ElectrumEvent =
+  | "Ping"
+  | { WindowMapped: { id: number; app_id: string | null; title: string | null } }
+  | { BeforeMap: { id: number; app_id: string | null; title: string | null } }
+  | { OutputAdded: { name: string; x: number; y: number; width: number; height: number } }
+  | { WindowUrgent: { id: number } }
+  | { MoveRequest: { id: number } }
+  | { ResizeRequest: { id: number; edges: number } }
+  | { MaximizeRequest: { id: number; output: string } }
+  | { UnmaximizeRequest: { id: number } }
+  | { UnfullscreenRequest: { id: number } }
+  | {
+    ProcessExited: {
+      handle: number;
+      code: number | null;
+      signal: number | null;
+      restarting: boolean;
+    };
+  }
+  | { Binding: { id: number; state: BindingState } }
+  | { Gesture: { id: number; update: GestureUpdate } }
+  | { OutputFrame: { output: string; time: number } }
+  | { Idle: { idle: boolean } }
+  | { ShortcutsInhibited: { inhibited: boolean } };
+
+export interface Binding {
+  id: number;
+  unbind(): void;
+}
+
+export interface KeyBindingOptions {
+  mode?: string;
+  onPress?: boolean;
+  onRelease?: boolean;
+  repeat?: boolean;
+}
+
+export interface ButtonBindingOptions {
+  mode?: string;
+  action?: "move" | "resize" | "event";
+}
+
+export interface AxisBindingOptions {
+  mode?: string;
+  root?: boolean;
+}
+
+export interface GestureBindingOptions {
+  mode?: string;
+  modifiers?: string;
+}
+
+export interface InitialLayout {
+  x?: number;
+  y?: number;
+  width?: number;
+  height?: number;
+  workspace?: number;
+  floating?: boolean;
+  decorations?: "client" | "server";
+}
+
+export interface WindowRule {
+  match?: {
+    appId?: string;
+    /** A regular expression, which may match anywhere in the title */
+    title?: string;
+    hasParent?: boolean;
+    pid?: number;
+  };
+  workspace?: number;
+  output?: string;
+  floating?: boolean;
+  width?: number;
+  height?: number;
+  /** From 0 for invisible to 1 for opaque */
+  opacity?: number;
+  fullscreen?: boolean;
+  neverFocus?: boolean;
+  decorations?: "client" | "server";
+}
+
+export interface SpawnOptions {
+  env?: Record<string, string>;
+  /** Restart the process whenever it exits unsuccessfully. Throws in web workers */
+  restart?: boolean;
+}
+
+export interface Process {
+  id: number;
+  kill(): void;
+}
+
+declare global {
+  const electrum: {
+    pollEvents(): Promise<ElectrumEvent | null>;
+    bindKey(
+      combo: string,
+      callback: (state: BindingState) => void,
+      options?: KeyBindingOptions,
+    ): Binding;
+    bindButton(
+      combo: string,
+      callback?: (state: BindingState) => void,
+      options?: ButtonBindingOptions,
+    ): Binding;
+    bindAxis(
+      combo: string,
+      callback: (state: BindingState) => void,
+      options?: AxisBindingOptions,
+    ): Binding;
+    bindGesture(
+      gesture: "swipe" | "pinch",
+      fingers: number,
+      callback: (update: GestureUpdate) => void,
+      options?: GestureBindingOptions,
+    ): Binding;
+    setBindingMode(mode: string): void;
+    setDecorationPolicy(policy: "preferServer" | "preferClient"): void;
+    setOutputScale(output: string, scale: number): void;
+    /** Milliseconds without input before the session is idle, or `null` to never be idle */
+    setIdleTimeout(timeout: number | null): void;
+    /** The combination taking the keys back from a client inhibiting shortcuts, or `null` for none */
+    setShortcutsInhibitEscape(combo: string | null): void;
+    /** Resolves with the time in milliseconds once the next frame of the output is presented */
+    requestOutputFrame(output: string): Promise<number>;
+    addWindowRule(rule: WindowRule): { id: number; remove(): void };
+    onBeforeMap(
+      callback:
+        | ((window: { id: number; app_id: string | null; title: string | null }) => InitialLayout | void)
+        | null,
+    ): void;
+    spawn(command: string, args?: string[], options?: SpawnOptions): Process;
+  };
+}
@@ -0,0 +1,43 @@
+use deno_ast::MediaType;
+use deno_core::ModuleSpecifier;
+
+pub const SCHEME: &str = "electrum";
+
+/// Modules under the `electrum:` scheme, built into the binary
+const MODULES: &[(&str, MediaType, &str)] = &[
+    (
+        "keybindings",
+        MediaType::TypeScript,
+        include_str!("std/keybindings.ts"),
+    ),
+    (
+        "layouts",
+        MediaType::TypeScript,
+        include_str!("std/layouts.ts"),
+    ),
+    ("types", MediaType::Dts, include_str!("std/types.d.ts")),
+];
+
+/// Maps the bare `electrum/<name>` specifiers onto `electrum:<name>`
+pub fn resolve(specifier: &str) -> Option<ModuleSpecifier> {
+    let name = specifier.strip_prefix("electrum/")?;
+    ModuleSpecifier::parse(&format!("{}:{}", SCHEME, name)).ok()
+}
+
+pub fn get(specifier: &ModuleSpecifier) -> Option<(MediaType, &'static str)> {
+    MODULES
+        .iter()
+        .find(|(name, _, _)| *name == specifier.path())
+        .map(|(_, media_type, code)| (*media_type, *code))
+}
+
+/// The built-in modules with the file names they would have on disk
+pub fn files() -> impl Iterator<Item = (&'static str, String, &'static str)> {
+    MODULES.iter().map(|(name, media_type, code)| {
+        (
+            *name,
+            format!("{}{}", name, media_type.as_ts_extension()),
+            *code,
+        )
+    })
+}
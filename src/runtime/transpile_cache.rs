@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use deno_ast::MediaType;
+use sha2::{Digest, Sha256};
+
+/// Transpiled modules on disk, keyed by a hash of everything that goes into transpiling them.
+/// Each deno_ast version gets a directory of its own, so the output of older ones can be dropped.
+#[derive(Clone)]
+pub struct TranspileCache {
+    dir: PathBuf,
+}
+
+impl TranspileCache {
+    pub fn new() -> Self {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("electrum").unwrap();
+        let cache = TranspileCache {
+            dir: xdg_dirs
+                .get_cache_home()
+                .join("gen")
+                .join(env!("DENO_AST_VERSION")),
+        };
+        cache.prune();
+        cache
+    }
+
+    /// Removes whatever other deno_ast versions left behind, as their keys can never match again
+    fn prune(&self) {
+        let gen = match self.dir.parent() {
+            Some(gen) => gen,
+            None => return,
+        };
+        let entries = match std::fs::read_dir(gen) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path == self.dir {
+                continue;
+            }
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            if let Err(err) = result {
+                slog_scope::warn!("Failed to prune transpile cache {:?}: {}", path, err);
+            }
+        }
+    }
+
+    /// The specifier is part of the key as it ends up in the inline source map
+    pub fn key(specifier: &str, media_type: MediaType, source: &str) -> String {
+        let media_type = media_type.to_string();
+        let mut hasher = Sha256::new();
+        for part in [
+            env!("DENO_AST_VERSION"),
+            specifier,
+            media_type.as_str(),
+            source,
+        ] {
+            hasher.update(part.len().to_le_bytes());
+            hasher.update(part);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.dir.join(key).with_extension("js")).ok()
+    }
+
+    pub fn insert(&self, key: &str, code: &str) {
+        let path = self.dir.join(key).with_extension("js");
+        // Written aside and renamed, so a half written module is never picked up
+        let temp = path.with_extension("js.tmp");
+        if let Err(err) = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&temp, code))
+            .and_then(|_| std::fs::rename(&temp, &path))
+        {
+            slog_scope::warn!("Failed to cache transpiled module {:?}: {}", path, err);
+        }
+    }
+}
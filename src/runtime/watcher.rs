@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Watches every file in the loaded module graph, signalling when one changes.
+///
+/// Parent directories are watched rather than the files themselves, as most editors
+/// save by replacing the file, which would silently drop a watch on the old inode.
+///
+/// Web workers share the main script's watcher, so editing a module only they load reloads
+/// the whole script.
+pub struct ModuleWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    modules: Arc<Mutex<HashSet<PathBuf>>>,
+    directories: Mutex<HashSet<PathBuf>>,
+}
+
+impl ModuleWatcher {
+    pub fn new() -> Result<(Self, UnboundedReceiver<()>), notify::Error> {
+        let (signal, receiver) = unbounded::<()>();
+        let modules = Arc::new(Mutex::new(HashSet::<PathBuf>::new()));
+
+        let watched = modules.clone();
+        let watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) => {
+                    let modules = watched.lock().unwrap();
+                    if event.paths.iter().any(|path| modules.contains(path)) {
+                        let _ = signal.unbounded_send(());
+                    }
+                }
+                Err(err) => slog_scope::warn!("Module watcher error: {}", err),
+            })?;
+
+        Ok((
+            ModuleWatcher {
+                watcher: Mutex::new(watcher),
+                modules,
+                directories: Mutex::new(HashSet::new()),
+            },
+            receiver,
+        ))
+    }
+
+    pub fn watch(&self, path: &Path) {
+        if !self.modules.lock().unwrap().insert(path.to_path_buf()) {
+            return;
+        }
+
+        if let Some(directory) = path.parent() {
+            if self
+                .directories
+                .lock()
+                .unwrap()
+                .insert(directory.to_path_buf())
+            {
+                if let Err(err) = self
+                    .watcher
+                    .lock()
+                    .unwrap()
+                    .watch(directory, RecursiveMode::NonRecursive)
+                {
+                    slog_scope::warn!("Failed to watch {:?}: {}", directory, err);
+                }
+            }
+        }
+    }
+}
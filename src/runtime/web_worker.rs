@@ -0,0 +1,73 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use deno_core::futures::task::LocalFutureObj;
+use deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel;
+use deno_runtime::deno_web::BlobStore;
+use deno_runtime::inspector_server::InspectorServer;
+use deno_runtime::ops::worker_host::{CreateWebWorkerCb, PreloadModuleCb};
+use deno_runtime::web_worker::{WebWorker, WebWorkerOptions};
+
+use super::errors::format_js_error;
+use super::extension::worker_extension;
+use super::main::{bootstrap_options, get_error_class_name};
+use super::module::TypescriptModuleLoader;
+use super::watcher::ModuleWatcher;
+
+/// What a web worker needs from the script that created it. Workers run on their own threads,
+/// so everything here has to be `Send`.
+#[derive(Clone)]
+pub struct WebWorkerConfig {
+    pub script_dir: PathBuf,
+    pub socket: OsString,
+    /// Editing a module only loaded by a worker still reloads the whole script
+    pub module_watcher: Arc<ModuleWatcher>,
+    pub blob_store: BlobStore,
+    pub broadcast_channel: InMemoryBroadcastChannel,
+    pub inspector_server: Option<Arc<InspectorServer>>,
+}
+
+pub fn create_web_worker_cb(config: WebWorkerConfig) -> Arc<CreateWebWorkerCb> {
+    Arc::new(move |args| {
+        let module_loader = Rc::new(TypescriptModuleLoader::new(
+            config.module_watcher.clone(),
+            &config.script_dir,
+            args.permissions.clone(),
+        ));
+
+        let options = WebWorkerOptions {
+            bootstrap: bootstrap_options(),
+            extensions: vec![worker_extension(config.socket.clone())],
+            unsafely_ignore_certificate_errors: None,
+            root_cert_store: None,
+            seed: None,
+            source_map_getter: Some(Box::new(module_loader.source_maps())),
+            module_loader,
+            create_web_worker_cb: create_web_worker_cb(config.clone()),
+            preload_module_cb: preload_module_cb(),
+            format_js_error_fn: Some(Arc::new(format_js_error)),
+            worker_type: args.worker_type,
+            maybe_inspector_server: config.inspector_server.clone(),
+            get_error_class_fn: Some(&get_error_class_name),
+            blob_store: config.blob_store.clone(),
+            broadcast_channel: config.broadcast_channel.clone(),
+            shared_array_buffer_store: None,
+            compiled_wasm_module_store: None,
+            stdio: Default::default(),
+        };
+
+        WebWorker::bootstrap_from_options(
+            args.name,
+            args.permissions,
+            args.main_module,
+            args.worker_id,
+            options,
+        )
+    })
+}
+
+pub fn preload_module_cb() -> Arc<PreloadModuleCb> {
+    Arc::new(|worker| LocalFutureObj::new(Box::new(async move { Ok(worker) })))
+}
@@ -0,0 +1,355 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::sync::Mutex;
+
+use deno_core::serde::Deserialize;
+use smithay::{
+    desktop::{Kind, Window},
+    reexports::{
+        wayland_protocols::xdg::{
+            decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+            shell::server::xdg_toplevel::{self, ResizeEdge},
+        },
+        wayland_server::DisplayHandle,
+    },
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::{
+        compositor::with_states,
+        seat::{PointerGrabStartData, Seat},
+        shell::xdg::{ToplevelSurface, XdgToplevelSurfaceRoleAttributes},
+        Serial,
+    },
+};
+
+use crate::{
+    input::bindings::BTN_LEFT,
+    state::{output::active_output, State},
+};
+
+use super::{layout::InitialLayout, window::WindowExt, Shell};
+
+pub const TITLE_HEIGHT: i32 = 24;
+pub const BORDER_WIDTH: i32 = 2;
+/// Space around the title bar buttons
+const BUTTON_MARGIN: i32 = 4;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DecorationMode {
+    Client,
+    Server,
+}
+
+impl From<Mode> for DecorationMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::ServerSide => DecorationMode::Server,
+            _ => DecorationMode::Client,
+        }
+    }
+}
+
+impl From<DecorationMode> for Mode {
+    fn from(mode: DecorationMode) -> Self {
+        match mode {
+            DecorationMode::Client => Mode::ClientSide,
+            DecorationMode::Server => Mode::ServerSide,
+        }
+    }
+}
+
+/// Who decorates windows that neither a window rule nor the script decided on
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DecorationPolicy {
+    /// The compositor draws title bars for every window negotiating decorations
+    PreferServer,
+    /// Clients decorate themselves unless they ask the compositor to
+    PreferClient,
+}
+
+impl Default for DecorationPolicy {
+    fn default() -> Self {
+        DecorationPolicy::PreferServer
+    }
+}
+
+/// The mode a client asked for, present once it created a decoration object
+struct RequestedDecorationMode(Mutex<Option<DecorationMode>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationPart {
+    TitleBar,
+    Close,
+    Maximize,
+    Border(ResizeEdge),
+}
+
+/// The area taken up by a window's decorations, given its geometry
+pub fn frame_geometry(geometry: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+    Rectangle::from_loc_and_size(
+        (
+            geometry.loc.x - BORDER_WIDTH,
+            geometry.loc.y - TITLE_HEIGHT - BORDER_WIDTH,
+        ),
+        (
+            geometry.size.w + 2 * BORDER_WIDTH,
+            geometry.size.h + TITLE_HEIGHT + 2 * BORDER_WIDTH,
+        ),
+    )
+}
+
+/// How far a window is from the corner of its frame, past the title bar and border if it has them
+pub fn frame_offset(window: &Window) -> Point<i32, Logical> {
+    if window.server_decorated() {
+        Point::from((BORDER_WIDTH, TITLE_HEIGHT + BORDER_WIDTH))
+    } else {
+        Point::from((0, 0))
+    }
+}
+
+/// The area left to a decorated window within the given frame
+pub fn content_geometry(frame: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+    Rectangle::from_loc_and_size(
+        (
+            frame.loc.x + BORDER_WIDTH,
+            frame.loc.y + TITLE_HEIGHT + BORDER_WIDTH,
+        ),
+        (
+            frame.size.w - 2 * BORDER_WIDTH,
+            frame.size.h - TITLE_HEIGHT - 2 * BORDER_WIDTH,
+        ),
+    )
+}
+
+/// The title bar, relative to the frame
+fn title_bar(frame: Size<i32, Logical>) -> Rectangle<i32, Logical> {
+    Rectangle::from_loc_and_size(
+        (BORDER_WIDTH, BORDER_WIDTH),
+        (frame.w - 2 * BORDER_WIDTH, TITLE_HEIGHT),
+    )
+}
+
+/// A title bar button relative to the frame, counting from the right
+fn button(frame: Size<i32, Logical>, index: i32) -> Rectangle<i32, Logical> {
+    let size = TITLE_HEIGHT - 2 * BUTTON_MARGIN;
+    Rectangle::from_loc_and_size(
+        (
+            frame.w - BORDER_WIDTH - (index + 1) * (size + BUTTON_MARGIN),
+            BORDER_WIDTH + BUTTON_MARGIN,
+        ),
+        (size, size),
+    )
+}
+
+pub fn close_button(frame: Size<i32, Logical>) -> Rectangle<i32, Logical> {
+    button(frame, 0)
+}
+
+pub fn maximize_button(frame: Size<i32, Logical>) -> Rectangle<i32, Logical> {
+    button(frame, 1)
+}
+
+/// Finds the part of the decorations at a point relative to the frame
+fn part_at(frame: Size<i32, Logical>, point: Point<f64, Logical>) -> Option<DecorationPart> {
+    if !Rectangle::from_loc_and_size((0, 0), frame)
+        .to_f64()
+        .contains(point)
+    {
+        return None;
+    }
+    if close_button(frame).to_f64().contains(point) {
+        return Some(DecorationPart::Close);
+    }
+    if maximize_button(frame).to_f64().contains(point) {
+        return Some(DecorationPart::Maximize);
+    }
+    if title_bar(frame).to_f64().contains(point) {
+        return Some(DecorationPart::TitleBar);
+    }
+
+    let left = point.x < BORDER_WIDTH as f64;
+    let right = point.x >= (frame.w - BORDER_WIDTH) as f64;
+    let top = point.y < BORDER_WIDTH as f64;
+    let bottom = point.y >= (frame.h - BORDER_WIDTH) as f64;
+    let edges = match (left, right, top, bottom) {
+        (true, _, true, _) => ResizeEdge::TopLeft,
+        (_, true, true, _) => ResizeEdge::TopRight,
+        (true, _, _, true) => ResizeEdge::BottomLeft,
+        (_, true, _, true) => ResizeEdge::BottomRight,
+        (true, _, _, _) => ResizeEdge::Left,
+        (_, true, _, _) => ResizeEdge::Right,
+        (_, _, true, _) => ResizeEdge::Top,
+        (_, _, _, true) => ResizeEdge::Bottom,
+        // Within the window itself
+        _ => return None,
+    };
+    Some(DecorationPart::Border(edges))
+}
+
+impl Shell {
+    /// The decorations a toplevel should get, or `None` if its client does not negotiate them.
+    /// `chosen` is what a window rule or the script decided on, if anything.
+    pub fn decoration_mode(
+        &self,
+        toplevel: &ToplevelSurface,
+        chosen: Option<DecorationMode>,
+    ) -> Option<DecorationMode> {
+        let requested = with_states(toplevel.wl_surface(), |states| {
+            states
+                .data_map
+                .get::<RequestedDecorationMode>()
+                .map(|requested| *requested.0.lock().unwrap())
+        })?;
+
+        Some(chosen.unwrap_or(match self.decoration_policy {
+            DecorationPolicy::PreferServer => DecorationMode::Server,
+            DecorationPolicy::PreferClient => requested.unwrap_or(DecorationMode::Client),
+        }))
+    }
+
+    /// Finds the decorations at a point on the active workspace, unless a window covers them
+    pub fn decoration_under(&self, point: Point<f64, Logical>) -> Option<(Window, DecorationPart)> {
+        let space = &self.active_workspace().space;
+        let covering = space.window_under(point);
+        let windows = space.windows().collect::<Vec<_>>();
+
+        for window in windows.into_iter().rev() {
+            if Some(window) == covering {
+                return None;
+            }
+            if !window.server_decorated() {
+                continue;
+            }
+
+            let mut geometry = window.geometry();
+            geometry.loc = space.window_location(window)?;
+            let frame = frame_geometry(geometry);
+            if let Some(part) = part_at(frame.size, point - frame.loc.to_f64()) {
+                return Some((window.clone(), part));
+            }
+        }
+        None
+    }
+}
+
+impl State {
+    /// Remembers the mode a client asked for and configures it with the mode it gets
+    pub fn update_decoration_mode(
+        &mut self,
+        toplevel: &ToplevelSurface,
+        requested: Option<DecorationMode>,
+    ) {
+        let initial_configure_sent = with_states(toplevel.wl_surface(), |states| {
+            states
+                .data_map
+                .insert_if_missing_threadsafe(|| RequestedDecorationMode(Mutex::new(None)));
+            *states
+                .data_map
+                .get::<RequestedDecorationMode>()
+                .unwrap()
+                .0
+                .lock()
+                .unwrap() = requested;
+
+            states
+                .data_map
+                .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .initial_configure_sent
+        });
+
+        // Until the initial configure, rules and the script may still decide on the mode
+        let chosen = self
+            .common
+            .shell
+            .window_for_surface(toplevel.wl_surface())
+            .and_then(|window| InitialLayout::get(&window))
+            .and_then(|layout| layout.decorations);
+        let mode = self.common.shell.decoration_mode(toplevel, chosen);
+        toplevel.with_pending_state(|state| state.decoration_mode = mode.map(Mode::from));
+        if initial_configure_sent {
+            toplevel.send_configure();
+        }
+    }
+
+    /// Switches the policy, reconfiguring every window left up to it
+    pub fn set_decoration_policy(&mut self, policy: DecorationPolicy) {
+        let shell = &mut self.common.shell;
+        if shell.decoration_policy == policy {
+            return;
+        }
+        shell.decoration_policy = policy;
+
+        for window in shell.windows() {
+            let chosen = InitialLayout::get(window).and_then(|layout| layout.decorations);
+            let toplevel = match window.toplevel() {
+                Kind::Xdg(toplevel) => toplevel,
+            };
+            if let Some(mode) = shell.decoration_mode(&toplevel, chosen) {
+                toplevel.with_pending_state(|state| state.decoration_mode = Some(mode.into()));
+                toplevel.send_configure();
+            }
+        }
+    }
+
+    /// Handles a button press on a window's decorations. Any button focuses the window, only the
+    /// left one moves, resizes, maximizes or closes it.
+    pub fn decoration_click(
+        &mut self,
+        dh: &DisplayHandle,
+        seat: &Seat<State>,
+        window: Window,
+        part: DecorationPart,
+        button: u32,
+        serial: Serial,
+    ) {
+        if self.common.shell.fallback_active {
+            self.common
+                .shell
+                .active_workspace_mut()
+                .space
+                .raise_window(&window, true);
+            self.common
+                .set_focus(dh, Some(window.toplevel().wl_surface()), seat, Some(serial));
+        }
+        if button != BTN_LEFT {
+            return;
+        }
+
+        let start_data = PointerGrabStartData {
+            focus: None,
+            button,
+            location: seat.get_pointer().unwrap().current_location(),
+        };
+        match part {
+            DecorationPart::TitleBar => self
+                .common
+                .shell
+                .move_request(&window, seat, serial, start_data),
+            DecorationPart::Close => match window.toplevel() {
+                Kind::Xdg(toplevel) => toplevel.send_close(),
+            },
+            DecorationPart::Maximize => {
+                let output = active_output(seat, &self.common);
+                let maximized = window
+                    .current_state()
+                    .states
+                    .contains(xdg_toplevel::State::Maximized);
+                let workspace = self.common.shell.active_workspace_mut();
+                if maximized {
+                    workspace.unmaximize_request(&window);
+                } else {
+                    workspace.maximize_request(&window, &output);
+                }
+            }
+            DecorationPart::Border(edges) => self
+                .common
+                .shell
+                .active_workspace_mut()
+                .resize_request(&window, seat, serial, start_data, edges),
+        }
+    }
+}
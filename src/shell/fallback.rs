@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Built-in window management policy.
+//! Used whenever there is no script to make these decisions, either because `main.ts` is missing or it has crashed.
+
+use std::cell::Cell;
+
+use smithay::{
+    desktop::{layer_map_for_output, Kind, Window},
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::{self, ResizeEdge},
+        wayland_server::DisplayHandle,
+    },
+    utils::{Logical, Point, Rectangle},
+    wayland::{
+        output::Output,
+        seat::{Focus, PointerGrabStartData, Seat},
+        Serial,
+    },
+};
+
+use crate::{
+    runtime::messages::RuntimeMessage,
+    state::{output::OutputExt, State},
+};
+
+use super::{
+    decorations::{content_geometry, frame_geometry},
+    grabs::{MoveSurfaceGrab, ResizeSurfaceGrab},
+    window::WindowExt,
+    Shell,
+};
+
+/// Where a maximized window was before, to put it back when unmaximized
+struct RestoreGeometry(Cell<Option<Rectangle<i32, Logical>>>);
+
+impl Shell {
+    /// Centers a new window on the output, within the area not reserved by layer surfaces
+    pub fn fallback_placement(&self, window: &Window, output: &Output) -> Point<i32, Logical> {
+        let mut zone = layer_map_for_output(output).non_exclusive_zone();
+        zone.loc += output.geometry().loc;
+        // Server-side decorations are centered along with the window
+        let geometry = Rectangle::from_loc_and_size((0, 0), window.geometry().size);
+        let frame = if window.server_decorated() {
+            frame_geometry(geometry)
+        } else {
+            geometry
+        };
+
+        Point::from((
+            zone.loc.x + ((zone.size.w - frame.size.w) / 2).max(0),
+            zone.loc.y + ((zone.size.h - frame.size.h) / 2).max(0),
+        )) - frame.loc
+    }
+
+    fn fallback_move(
+        &mut self,
+        window: &Window,
+        seat: &Seat<State>,
+        serial: Serial,
+        start_data: PointerGrabStartData,
+    ) {
+        let workspace = match self.space_for_window_mut(window.toplevel().wl_surface()) {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        let initial_window_location = match workspace.space.window_location(window) {
+            Some(location) => location,
+            None => return,
+        };
+
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.set_grab(
+                MoveSurfaceGrab {
+                    start_data,
+                    window: window.clone(),
+                    initial_window_location,
+                },
+                serial,
+                Focus::Clear,
+            );
+        }
+    }
+
+    fn fallback_resize(
+        &mut self,
+        window: &Window,
+        seat: &Seat<State>,
+        serial: Serial,
+        start_data: PointerGrabStartData,
+        edges: ResizeEdge,
+    ) {
+        let workspace = match self.space_for_window_mut(window.toplevel().wl_surface()) {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        let mut initial_window_geometry = window.geometry();
+        initial_window_geometry.loc = match workspace.space.window_location(window) {
+            Some(location) => location,
+            None => return,
+        };
+
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.set_grab(
+                ResizeSurfaceGrab {
+                    start_data,
+                    window: window.clone(),
+                    edges,
+                    initial_window_geometry,
+                },
+                serial,
+                Focus::Clear,
+            );
+        }
+    }
+
+    fn fallback_maximize(&mut self, window: &Window, output: &Output) {
+        let mut zone = layer_map_for_output(output).non_exclusive_zone();
+        zone.loc += output.geometry().loc;
+        if window.server_decorated() {
+            zone = content_geometry(zone);
+        }
+
+        #[allow(irrefutable_let_patterns)]
+        if let Kind::Xdg(xdg) = window.toplevel() {
+            xdg.with_pending_state(|state| {
+                state.states.set(xdg_toplevel::State::Maximized);
+                state.size = Some(zone.size);
+            });
+            xdg.send_configure();
+        }
+
+        if let Some(workspace) = self.space_for_window_mut(window.toplevel().wl_surface()) {
+            // Maximizing again keeps the geometry from before the first time
+            let userdata = window.user_data();
+            userdata.insert_if_missing(|| RestoreGeometry(Cell::new(None)));
+            let restore = &userdata.get::<RestoreGeometry>().unwrap().0;
+            if restore.get().is_none() {
+                if let Some(loc) = workspace.space.window_location(window) {
+                    restore.set(Some(Rectangle::from_loc_and_size(
+                        loc,
+                        window.geometry().size,
+                    )));
+                }
+            }
+            workspace.space.map_window(window, zone.loc, None, true);
+        }
+    }
+
+    fn fallback_unmaximize(&mut self, window: &Window) {
+        let restore = window
+            .user_data()
+            .get::<RestoreGeometry>()
+            .and_then(|restore| restore.0.take());
+
+        #[allow(irrefutable_let_patterns)]
+        if let Kind::Xdg(xdg) = window.toplevel() {
+            xdg.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Maximized);
+                state.size = restore.map(|geometry| geometry.size);
+            });
+            xdg.send_configure();
+        }
+
+        if let Some(restore) = restore {
+            if let Some(workspace) = self.space_for_window_mut(window.toplevel().wl_surface()) {
+                workspace.space.map_window(window, restore.loc, None, true);
+            }
+        }
+    }
+}
+
+impl State {
+    /// Handles a request the script would otherwise have answered
+    pub fn default_request(&mut self, _dh: &DisplayHandle, message: &RuntimeMessage) {
+        let shell = &mut self.common.shell;
+        match message {
+            RuntimeMessage::MoveRequest {
+                window,
+                seat,
+                serial,
+                start_data,
+            } => shell.fallback_move(window, seat, *serial, start_data.clone()),
+            RuntimeMessage::ResizeRequest {
+                window,
+                seat,
+                serial,
+                start_data,
+                edges,
+            } => shell.fallback_resize(window, seat, *serial, start_data.clone(), *edges),
+            RuntimeMessage::MaximizeRequest { window, output } => {
+                shell.fallback_maximize(window, output)
+            }
+            RuntimeMessage::UnmaximizeRequest { window } => shell.fallback_unmaximize(window),
+            _ => {}
+        }
+    }
+}
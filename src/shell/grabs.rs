@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    desktop::{Kind, Window},
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::{self, ResizeEdge},
+        wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle},
+    },
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::seat::{
+        AxisFrame, ButtonEvent, MotionEvent, PointerGrab, PointerGrabStartData, PointerInnerHandle,
+    },
+};
+
+use crate::state::State;
+
+pub struct MoveSurfaceGrab {
+    pub start_data: PointerGrabStartData,
+    pub window: Window,
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl PointerGrab<State> for MoveSurfaceGrab {
+    fn motion(
+        &mut self,
+        data: &mut State,
+        _dh: &DisplayHandle,
+        handle: &mut PointerInnerHandle<'_, State>,
+        _focus: Option<(WlSurface, Point<i32, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(event.location, None, event.serial, event.time);
+
+        let delta = event.location - self.start_data.location;
+        let new_location = self.initial_window_location.to_f64() + delta;
+
+        if let Some(workspace) = data
+            .common
+            .shell
+            .space_for_window_mut(self.window.toplevel().wl_surface())
+        {
+            workspace
+                .space
+                .map_window(&self.window, new_location.to_i32_round(), None, true);
+        }
+    }
+
+    fn button(
+        &mut self,
+        _data: &mut State,
+        dh: &DisplayHandle,
+        handle: &mut PointerInnerHandle<'_, State>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(event.button, event.state, event.serial, event.time);
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(dh, event.serial, event.time);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        _data: &mut State,
+        _dh: &DisplayHandle,
+        handle: &mut PointerInnerHandle<'_, State>,
+        details: AxisFrame,
+    ) {
+        handle.axis(details)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData {
+        &self.start_data
+    }
+}
+
+pub struct ResizeSurfaceGrab {
+    pub start_data: PointerGrabStartData,
+    pub window: Window,
+    pub edges: ResizeEdge,
+    pub initial_window_geometry: Rectangle<i32, Logical>,
+}
+
+impl ResizeSurfaceGrab {
+    /// Returns which of the (left, right, top, bottom) edges are being dragged
+    fn dragged_edges(&self) -> (bool, bool, bool, bool) {
+        match self.edges {
+            ResizeEdge::Left => (true, false, false, false),
+            ResizeEdge::Right => (false, true, false, false),
+            ResizeEdge::Top => (false, false, true, false),
+            ResizeEdge::Bottom => (false, false, false, true),
+            ResizeEdge::TopLeft => (true, false, true, false),
+            ResizeEdge::TopRight => (false, true, true, false),
+            ResizeEdge::BottomLeft => (true, false, false, true),
+            ResizeEdge::BottomRight => (false, true, false, true),
+            _ => (false, false, false, false),
+        }
+    }
+}
+
+impl PointerGrab<State> for ResizeSurfaceGrab {
+    fn motion(
+        &mut self,
+        data: &mut State,
+        _dh: &DisplayHandle,
+        handle: &mut PointerInnerHandle<'_, State>,
+        _focus: Option<(WlSurface, Point<i32, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        handle.motion(event.location, None, event.serial, event.time);
+
+        let delta = (event.location - self.start_data.location).to_i32_round::<i32>();
+        let (left, right, top, bottom) = self.dragged_edges();
+        let initial = self.initial_window_geometry;
+
+        let mut width = initial.size.w;
+        if left {
+            width -= delta.x;
+        } else if right {
+            width += delta.x;
+        }
+        let mut height = initial.size.h;
+        if top {
+            height -= delta.y;
+        } else if bottom {
+            height += delta.y;
+        }
+        let size = Size::from((width.max(1), height.max(1)));
+
+        #[allow(irrefutable_let_patterns)]
+        if let Kind::Xdg(toplevel) = self.window.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.set(xdg_toplevel::State::Resizing);
+                state.size = Some(size);
+            });
+            toplevel.send_configure();
+        }
+
+        // Dragging the top or left edge keeps the opposite edge in place
+        if left || top {
+            let mut location = initial.loc;
+            if left {
+                location.x += initial.size.w - size.w;
+            }
+            if top {
+                location.y += initial.size.h - size.h;
+            }
+            if let Some(workspace) = data
+                .common
+                .shell
+                .space_for_window_mut(self.window.toplevel().wl_surface())
+            {
+                workspace
+                    .space
+                    .map_window(&self.window, location, None, true);
+            }
+        }
+    }
+
+    fn button(
+        &mut self,
+        _data: &mut State,
+        dh: &DisplayHandle,
+        handle: &mut PointerInnerHandle<'_, State>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(event.button, event.state, event.serial, event.time);
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(dh, event.serial, event.time);
+
+            #[allow(irrefutable_let_patterns)]
+            if let Kind::Xdg(toplevel) = self.window.toplevel() {
+                toplevel.with_pending_state(|state| {
+                    state.states.unset(xdg_toplevel::State::Resizing);
+                });
+                toplevel.send_configure();
+            }
+        }
+    }
+
+    fn axis(
+        &mut self,
+        _data: &mut State,
+        _dh: &DisplayHandle,
+        handle: &mut PointerInnerHandle<'_, State>,
+        details: AxisFrame,
+    ) {
+        handle.axis(details)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData {
+        &self.start_data
+    }
+}
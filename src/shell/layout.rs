@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use deno_core::serde::Deserialize;
+use smithay::{
+    desktop::{Kind, Window},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            RegistrationToken,
+        },
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+        wayland_server::DisplayHandle,
+    },
+    utils::IsAlive,
+};
+
+use crate::{runtime::messages::RuntimeMessage, state::State};
+
+use super::{decorations::DecorationMode, window::WindowExt};
+
+/// How long a new window may wait on the script before it is configured without its answer
+pub const BEFORE_MAP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Where and how a new window starts out, as decided by the script's before-map hook.
+/// Anything left out is up to the client and the compositor's defaults.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InitialLayout {
+    /// Position in the global coordinate space, like the outputs the script is told about
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub workspace: Option<u8>,
+    pub floating: Option<bool>,
+    pub decorations: Option<DecorationMode>,
+}
+
+impl InitialLayout {
+    pub fn get(window: &Window) -> Option<InitialLayout> {
+        window
+            .user_data()
+            .get::<RefCell<InitialLayout>>()
+            .map(|layout| layout.borrow().clone())
+    }
+
+    fn set(self, window: &Window) {
+        window
+            .user_data()
+            .insert_if_missing(|| RefCell::new(InitialLayout::default()));
+        *window
+            .user_data()
+            .get::<RefCell<InitialLayout>>()
+            .unwrap()
+            .borrow_mut() = self;
+    }
+}
+
+impl State {
+    /// Asks the script to lay out a new window, holding back its initial configure until the
+    /// script answers or `BEFORE_MAP_TIMEOUT` passes
+    pub fn request_initial_layout(&mut self, window: &Window) {
+        let shell = &mut self.common.shell;
+        if shell.pending_layouts.iter().any(|(w, _)| w == window) {
+            return;
+        }
+
+        let id = window.id();
+        let timer = self
+            .common
+            .event_loop_handle
+            .insert_source(
+                Timer::from_duration(BEFORE_MAP_TIMEOUT),
+                move |_, _, data| {
+                    slog_scope::warn!("The script took too long to lay out window {}", id);
+                    let dh = data.display.handle();
+                    data.state.apply_initial_layout(&dh, id, None);
+                    TimeoutAction::Drop
+                },
+            )
+            .expect("Failed to initialise layout timer");
+        shell.pending_layouts.push((window.clone(), timer));
+
+        shell
+            .active_workspace()
+            .runtime_sender
+            .send(RuntimeMessage::BeforeMap {
+                window: window.clone(),
+            })
+            .unwrap();
+    }
+
+    /// Sends the initial configure of a window waiting on the script, with the script's layout
+    /// if it gave one
+    pub fn apply_initial_layout(
+        &mut self,
+        dh: &DisplayHandle,
+        id: u32,
+        layout: Option<InitialLayout>,
+    ) {
+        let shell = &mut self.common.shell;
+        let (window, timer) = match shell.pending_layouts.iter().position(|(w, _)| w.id() == id) {
+            Some(pos) => shell.pending_layouts.remove(pos),
+            None => return,
+        };
+        self.common.event_loop_handle.remove(timer);
+        self.configure_initial(dh, &window, layout);
+    }
+
+    /// Stops waiting on the script for every window, for when it goes away
+    pub fn flush_initial_layouts(&mut self, dh: &DisplayHandle) {
+        for (window, timer) in std::mem::take(&mut self.common.shell.pending_layouts) {
+            self.common.event_loop_handle.remove(timer);
+            self.configure_initial(dh, &window, None);
+        }
+    }
+
+    /// Sends a window's initial configure, with the window rules filling in anything the
+    /// script's layout leaves out
+    #[allow(irrefutable_let_patterns)]
+    pub fn configure_initial(
+        &self,
+        dh: &DisplayHandle,
+        window: &Window,
+        layout: Option<InitialLayout>,
+    ) {
+        let properties = self.common.shell.rules.properties(dh, window);
+        let mut layout = layout.unwrap_or_default();
+        layout.width = layout.width.or(properties.width);
+        layout.height = layout.height.or(properties.height);
+        layout.workspace = layout.workspace.or(properties.workspace);
+        layout.floating = layout.floating.or(properties.floating);
+        layout.decorations = layout.decorations.or(properties.decorations);
+
+        if let Kind::Xdg(toplevel) = window.toplevel() {
+            if !toplevel.alive() {
+                return;
+            }
+            let decorations = self
+                .common
+                .shell
+                .decoration_mode(&toplevel, layout.decorations);
+
+            toplevel.with_pending_state(|state| {
+                state.size = match (layout.width, layout.height) {
+                    (Some(width), Some(height)) => Some((width, height).into()),
+                    _ => None,
+                };
+                // Tiled clients drop their shadows and rounded corners
+                if layout.floating == Some(false) {
+                    state.states.set(xdg_toplevel::State::TiledLeft);
+                    state.states.set(xdg_toplevel::State::TiledRight);
+                    state.states.set(xdg_toplevel::State::TiledTop);
+                    state.states.set(xdg_toplevel::State::TiledBottom);
+                }
+                state.decoration_mode = decorations.map(Into::into);
+            });
+            toplevel.send_configure();
+        }
+        layout.set(window);
+    }
+}
@@ -1,7 +1,7 @@
 //! This Implementation, ideally, will purely be callbacks to an FFI.
 //! There should be minimal amounts of code here, any code here is either for debug purposes or in development
 
-use calloop::channel::Sender;
+use calloop::{channel::Sender, RegistrationToken};
 use smithay::{
     desktop::{layer_map_for_output, LayerSurface, PopupManager, Window, WindowSurfaceType},
     reexports::wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle},
@@ -16,15 +16,27 @@ use smithay::{
             },
             xdg::{PopupSurface, PositionerState, XdgShellState},
         },
-        Serial,
+        Serial, SERIAL_COUNTER,
     },
 };
 
+pub mod decorations;
+pub mod fallback;
+pub mod grabs;
+pub mod layout;
+pub mod rules;
+pub mod window;
 pub mod workspace;
 
 use crate::{runtime::messages::RuntimeMessage, state::State};
 
-use self::workspace::Workspace;
+use self::{
+    decorations::{frame_offset, DecorationPolicy},
+    layout::InitialLayout,
+    rules::{WindowProperties, WindowRules, RULES_FILE},
+    window::WindowExt,
+    workspace::Workspace,
+};
 
 pub struct Shell {
     pub workspaces: Vec<Workspace>,
@@ -33,6 +45,15 @@ pub struct Shell {
 
     pub pending_windows: Vec<(Window, Seat<State>)>,
     pub pending_layers: Vec<(LayerSurface, Output, Seat<State>)>,
+    /// Windows whose initial configure waits on the script, with their timeout
+    pub pending_layouts: Vec<(Window, RegistrationToken)>,
+    /// Whether the script lays out new windows before they are configured
+    pub before_map_hook: bool,
+
+    /// Whether the built-in policy is standing in for the script
+    pub fallback_active: bool,
+    pub rules: WindowRules,
+    pub decoration_policy: DecorationPolicy,
 
     // Wayland State
     pub layer_shell_state: WlrLayerShellState,
@@ -49,6 +70,12 @@ impl Shell {
 
             pending_windows: Vec::new(),
             pending_layers: Vec::new(),
+            pending_layouts: Vec::new(),
+            before_map_hook: false,
+
+            fallback_active: false,
+            rules: WindowRules::new(crate::runtime::config_path().with_file_name(RULES_FILE)),
+            decoration_policy: DecorationPolicy::default(),
 
             layer_shell_state: WlrLayerShellState::new::<State, _>(dh, slog_scope::logger()),
             xdg_shell_state: XdgShellState::new::<State, _>(dh, slog_scope::logger()),
@@ -59,6 +86,12 @@ impl Shell {
         self.outputs.iter()
     }
 
+    pub fn windows(&self) -> impl Iterator<Item = &Window> {
+        self.workspaces
+            .iter()
+            .flat_map(|workspace| workspace.space.windows())
+    }
+
     pub fn active_workspace(&self) -> &Workspace {
         &self.workspaces.get(0).unwrap()
     }
@@ -67,6 +100,15 @@ impl Shell {
         &mut self.workspaces[0]
     }
 
+    pub fn window_for_surface(&self, surface: &WlSurface) -> Option<Window> {
+        self.workspaces.iter().find_map(|workspace| {
+            workspace
+                .space
+                .window_for_surface(surface, WindowSurfaceType::ALL)
+                .cloned()
+        })
+    }
+
     pub fn space_for_window_mut(&mut self, surface: &WlSurface) -> Option<&mut Workspace> {
         self.workspaces.iter_mut().find(|workspace| {
             workspace
@@ -85,6 +127,13 @@ impl Shell {
             0,
             output.current_location(),
         );
+
+        self.active_workspace()
+            .runtime_sender
+            .send(RuntimeMessage::OutputAdded {
+                output: output.clone(),
+            })
+            .unwrap();
     }
 
     pub fn remove_output(&mut self, output: &Output) {
@@ -99,6 +148,7 @@ impl Shell {
                 .space
                 .map_output(output, output.current_location());
         }
+        self.update_preferred_scales();
     }
 
     pub fn refresh(&mut self, dh: &DisplayHandle) {
@@ -109,6 +159,7 @@ impl Shell {
             let mut map = layer_map_for_output(output);
             map.cleanup(dh);
         }
+        self.update_window_scales();
     }
 
     pub fn map_layer(&mut self, layer_surface: &LayerSurface, dh: &DisplayHandle) {
@@ -128,20 +179,54 @@ impl Shell {
             })
         };
 
-        let mut map = layer_map_for_output(&output);
-        map.map_layer(dh, &layer_surface).unwrap();
+        layer_map_for_output(&output)
+            .map_layer(dh, &layer_surface)
+            .unwrap();
+        self.update_preferred_scales();
 
         if wants_focus {
             self.set_focus(dh, Some(surface), &seat, None)
         }
     }
 
-    pub fn map_window(&mut self, window: &Window, _output: &Output, _dh: &DisplayHandle) {
-        let workspace = self.active_workspace_mut();
+    pub fn map_window(&mut self, window: &Window, output: &Output, dh: &DisplayHandle) {
+        self.pending_windows.retain(|(w, _)| w != window);
+
+        let properties = self.rules.properties(dh, window);
+        let output = properties
+            .output
+            .as_ref()
+            .and_then(|name| self.outputs.iter().find(|o| o.name() == *name))
+            .unwrap_or(output)
+            .clone();
+        let layout = InitialLayout::get(window).unwrap_or_default();
+        let location = match (layout.x, layout.y) {
+            (Some(x), Some(y)) => Point::from((x, y)),
+            _ if self.fallback_active || properties.output.is_some() => {
+                self.fallback_placement(window, &output)
+            }
+            // The title bar of a server-side decorated window goes above it, so it has to fit
+            _ => frame_offset(window),
+        };
+        let workspace = match layout.workspace {
+            Some(idx) if (idx as usize) < self.workspaces.len() => {
+                &mut self.workspaces[idx as usize]
+            }
+            _ => self.active_workspace_mut(),
+        };
+
+        workspace.space.map_window(window, location, 0, false);
+        if properties.fullscreen == Some(true) {
+            workspace.fullscreen_request(window, &output);
+        }
+        properties.set(window);
 
         workspace
-            .space
-            .map_window(window, Point::from((0, 0)), 0, false);
+            .runtime_sender
+            .send(RuntimeMessage::WindowMapped {
+                window: window.clone(),
+            })
+            .unwrap();
     }
 
     /// Deno Function
@@ -175,17 +260,95 @@ impl Shell {
     /// Deno Function
     pub fn set_focus(
         &mut self,
-        _dh: &DisplayHandle,
-        _surface: Option<&WlSurface>,
-        _active_seat: &Seat<State>,
-        _serial: Option<Serial>,
+        dh: &DisplayHandle,
+        surface: Option<&WlSurface>,
+        active_seat: &Seat<State>,
+        serial: Option<Serial>,
     ) {
-        // TODO: Focus
+        let never_focus = surface
+            .and_then(|surface| self.window_for_surface(surface))
+            .map(|window| WindowProperties::get(&window).never_focus == Some(true))
+            .unwrap_or(false);
+        if never_focus {
+            return;
+        }
+
+        if let Some(keyboard) = active_seat.get_keyboard() {
+            keyboard.set_focus(
+                dh,
+                surface,
+                serial.unwrap_or_else(|| SERIAL_COUNTER.next_serial()),
+            );
+        }
     }
 
     /// Deno Function
-    pub fn update_active<'a>(&mut self, _seats: impl Iterator<Item = &'a Seat<State>>) {
-        // TODO: Focus
+    pub fn update_active<'a>(&mut self, seats: impl Iterator<Item = &'a Seat<State>>) {
+        let focused = seats
+            .filter_map(|seat| seat.get_keyboard())
+            .filter_map(|keyboard| keyboard.current_focus())
+            .collect::<Vec<_>>();
+
+        for workspace in &self.workspaces {
+            for window in workspace.space.windows() {
+                let surface = window.toplevel().wl_surface();
+                let activated = focused.iter().any(|focus| focus == surface);
+                if activated {
+                    window.set_urgent(false);
+                }
+                if window.set_activated(activated) {
+                    window.configure();
+                }
+            }
+        }
+    }
+
+    /// Finds the surface under the given point on the active workspace, with its location
+    pub fn surface_under(
+        &self,
+        output: &Output,
+        point: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let layers = layer_map_for_output(output);
+        let output_location = output.current_location();
+        let layer_under = |layer| {
+            layers
+                .layer_under(layer, point - output_location.to_f64())
+                .and_then(|layer_surface| {
+                    let location =
+                        layers.layer_geometry(layer_surface).unwrap().loc + output_location;
+                    layer_surface
+                        .surface_under(point - location.to_f64(), WindowSurfaceType::ALL)
+                        .map(|(surface, loc)| (surface, loc + location))
+                })
+        };
+
+        let workspace = self.active_workspace();
+        if let Some(window) = workspace.get_fullscreen(output) {
+            return window
+                .surface_under(point - output_location.to_f64(), WindowSurfaceType::ALL)
+                .map(|(surface, loc)| (surface, loc + output_location));
+        }
+
+        if let Some(under) = layer_under(Layer::Overlay).or_else(|| layer_under(Layer::Top)) {
+            return Some(under);
+        }
+        // Title bars hide whatever is below them
+        if self.decoration_under(point).is_some() {
+            return None;
+        }
+
+        workspace
+            .space
+            .window_under(point)
+            .and_then(|window| {
+                let location = workspace.space.window_location(window).unwrap();
+                window
+                    .surface_under(point - location.to_f64(), WindowSurfaceType::ALL)
+                    .map(|(surface, loc)| (surface, loc + location))
+            })
+            .or_else(|| layer_under(Layer::Bottom))
+            .or_else(|| layer_under(Layer::Background))
     }
 
     /// Deno Function
@@ -0,0 +1,352 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use deno_core::serde::Deserialize;
+use deno_core::serde_json;
+use regex::Regex;
+use smithay::{
+    desktop::{Kind, Window},
+    reexports::wayland_server::{DisplayHandle, Resource},
+};
+
+use super::{decorations::DecorationMode, window::WindowExt};
+
+pub const RULES_FILE: &str = "rules.json";
+
+/// What a rule matches on. Every given field has to match.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowMatch {
+    pub app_id: Option<String>,
+    /// A regular expression, which may match anywhere in the title
+    pub title: Option<String>,
+    pub has_parent: Option<bool>,
+    pub pid: Option<i32>,
+}
+
+/// What a rule applies to the windows it matches. Later rules override earlier ones.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowProperties {
+    pub workspace: Option<u8>,
+    pub output: Option<String>,
+    pub floating: Option<bool>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// From 0 for invisible to 1 for opaque
+    pub opacity: Option<f32>,
+    pub fullscreen: Option<bool>,
+    pub never_focus: Option<bool>,
+    pub decorations: Option<DecorationMode>,
+}
+
+impl WindowProperties {
+    fn merge(&mut self, other: &WindowProperties) {
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field.clone();
+                })*
+            };
+        }
+        merge!(
+            workspace,
+            output,
+            floating,
+            width,
+            height,
+            opacity,
+            fullscreen,
+            never_focus,
+            decorations
+        );
+    }
+
+    /// The properties applied to a window when it was mapped
+    pub fn get(window: &Window) -> WindowProperties {
+        window
+            .user_data()
+            .get::<RefCell<WindowProperties>>()
+            .map(|properties| properties.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set(self, window: &Window) {
+        window
+            .user_data()
+            .insert_if_missing(|| RefCell::new(WindowProperties::default()));
+        *window
+            .user_data()
+            .get::<RefCell<WindowProperties>>()
+            .unwrap()
+            .borrow_mut() = self;
+    }
+}
+
+/// A rule as written in `rules.json` or passed to `electrum.addWindowRule()`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowRuleConfig {
+    #[serde(rename = "match", default)]
+    pub matches: WindowMatch,
+    #[serde(flatten)]
+    pub properties: WindowProperties,
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowRule {
+    app_id: Option<String>,
+    title: Option<Regex>,
+    has_parent: Option<bool>,
+    pid: Option<i32>,
+    properties: WindowProperties,
+}
+
+impl TryFrom<WindowRuleConfig> for WindowRule {
+    type Error = Error;
+
+    fn try_from(config: WindowRuleConfig) -> Result<Self, Self::Error> {
+        Ok(WindowRule {
+            app_id: config.matches.app_id,
+            title: config
+                .matches
+                .title
+                .as_deref()
+                .map(Regex::new)
+                .transpose()?,
+            has_parent: config.matches.has_parent,
+            pid: config.matches.pid,
+            properties: config.properties,
+        })
+    }
+}
+
+/// What rules are matched against
+#[derive(Debug, Default)]
+struct WindowInfo {
+    app_id: Option<String>,
+    title: Option<String>,
+    has_parent: bool,
+    pid: Option<i32>,
+}
+
+impl WindowInfo {
+    fn new(dh: &DisplayHandle, window: &Window) -> Self {
+        let toplevel = match window.toplevel() {
+            Kind::Xdg(toplevel) => toplevel,
+        };
+        WindowInfo {
+            app_id: window.app_id(),
+            title: window.title(),
+            has_parent: toplevel.parent().is_some(),
+            pid: dh
+                .get_client(toplevel.wl_surface().id())
+                .ok()
+                .and_then(|client| client.get_credentials(dh).ok())
+                .map(|credentials| credentials.pid),
+        }
+    }
+}
+
+impl WindowRule {
+    fn matches(&self, window: &WindowInfo) -> bool {
+        if self.app_id.is_some() && self.app_id != window.app_id {
+            return false;
+        }
+        if let Some(title) = &self.title {
+            if !title.is_match(window.title.as_deref().unwrap_or_default()) {
+                return false;
+            }
+        }
+        if let Some(has_parent) = self.has_parent {
+            if window.has_parent != has_parent {
+                return false;
+            }
+        }
+        if self.pid.is_some() && self.pid != window.pid {
+            return false;
+        }
+        true
+    }
+}
+
+/// Rules from `rules.json`, followed by the ones the script added
+pub struct WindowRules {
+    path: PathBuf,
+    file_rules: Vec<WindowRule>,
+    script_rules: Vec<(u32, WindowRule)>,
+}
+
+impl WindowRules {
+    pub fn new(path: PathBuf) -> Self {
+        let mut rules = WindowRules {
+            path,
+            file_rules: Vec::new(),
+            script_rules: Vec::new(),
+        };
+        rules.reload();
+        rules
+    }
+
+    /// Re-reads `rules.json` and drops the script's rules, for when the script is reloaded
+    pub fn reload(&mut self) {
+        self.script_rules.clear();
+        self.file_rules = match load(&self.path) {
+            Ok(rules) => rules,
+            Err(err) => {
+                slog_scope::error!("Failed to read {:?}, ignoring it: {}", self.path, err);
+                Vec::new()
+            }
+        };
+    }
+
+    pub fn add(&mut self, id: u32, rule: WindowRule) {
+        self.script_rules.push((id, rule));
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.script_rules.retain(|(rule_id, _)| *rule_id != id);
+    }
+
+    pub fn properties(&self, dh: &DisplayHandle, window: &Window) -> WindowProperties {
+        self.properties_for(&WindowInfo::new(dh, window))
+    }
+
+    fn properties_for(&self, window: &WindowInfo) -> WindowProperties {
+        let mut properties = WindowProperties::default();
+        for rule in self
+            .file_rules
+            .iter()
+            .chain(self.script_rules.iter().map(|(_, rule)| rule))
+            .filter(|rule| rule.matches(window))
+        {
+            properties.merge(&rule.properties);
+        }
+        properties
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<WindowRule>, Error> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let configs: Vec<WindowRuleConfig> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    configs.into_iter().map(WindowRule::try_from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_rule(config: &str) -> WindowRule {
+        serde_json::from_str::<WindowRuleConfig>(config)
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    fn window(app_id: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            app_id: Some(app_id.to_string()),
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn load_rules(file: &str) -> (tempfile::TempDir, WindowRules) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(RULES_FILE);
+        std::fs::write(&path, file).unwrap();
+        let rules = WindowRules::new(path);
+        (dir, rules)
+    }
+
+    #[test]
+    fn matches_every_given_field() {
+        let rule =
+            parse_rule(r#"{ "match": { "appId": "foot", "title": "^vim" }, "floating": true }"#);
+        assert!(rule.matches(&window("foot", "vim main.rs")));
+        assert!(!rule.matches(&window("foot", "htop")));
+        assert!(!rule.matches(&window("alacritty", "vim main.rs")));
+
+        let rule = parse_rule(r#"{ "match": { "hasParent": true, "pid": 42 } }"#);
+        let mut dialog = window("firefox", "Save as");
+        assert!(!rule.matches(&dialog));
+        dialog.has_parent = true;
+        dialog.pid = Some(42);
+        assert!(rule.matches(&dialog));
+        dialog.pid = None;
+        assert!(!rule.matches(&dialog));
+
+        // Without a match everything matches
+        assert!(parse_rule(r#"{ "opacity": 0.9 }"#).matches(&WindowInfo::default()));
+    }
+
+    #[test]
+    fn rejects_invalid_titles() {
+        let config: WindowRuleConfig =
+            serde_json::from_str(r#"{ "match": { "title": "(" } }"#).unwrap();
+        assert!(WindowRule::try_from(config).is_err());
+    }
+
+    #[test]
+    fn parses_the_rules_file() {
+        let (_dir, rules) = load_rules(
+            r#"[
+                { "match": { "appId": "mpv" }, "workspace": 2, "opacity": 0.8 },
+                { "match": { "appId": "foot" }, "decorations": "server", "neverFocus": true }
+            ]"#,
+        );
+        let properties = rules.properties_for(&window("mpv", ""));
+        assert_eq!(properties.workspace, Some(2));
+        assert_eq!(properties.opacity, Some(0.8));
+        assert_eq!(properties.never_focus, None);
+
+        let properties = rules.properties_for(&window("foot", ""));
+        assert_eq!(properties.decorations, Some(DecorationMode::Server));
+        assert_eq!(properties.never_focus, Some(true));
+    }
+
+    #[test]
+    fn ignores_a_broken_rules_file() {
+        let (_dir, rules) = load_rules("[{ \"match\": ");
+        assert!(rules.file_rules.is_empty());
+        let (_dir, rules) = load_rules(r#"[{ "match": { "title": "(" } }]"#);
+        assert!(rules.file_rules.is_empty());
+    }
+
+    #[test]
+    fn script_rules_override_file_rules() {
+        let (_dir, mut rules) = load_rules(
+            r#"[
+                { "match": { "appId": "foot" }, "width": 800, "height": 600 },
+                { "width": 640 }
+            ]"#,
+        );
+        // Later file rules override earlier ones
+        let properties = rules.properties_for(&window("foot", ""));
+        assert_eq!(properties.width, Some(640));
+        assert_eq!(properties.height, Some(600));
+
+        rules.add(
+            1,
+            parse_rule(r#"{ "match": { "appId": "foot" }, "height": 400 }"#),
+        );
+        rules.add(2, parse_rule(r#"{ "floating": true }"#));
+        let properties = rules.properties_for(&window("foot", ""));
+        assert_eq!(properties.width, Some(640));
+        assert_eq!(properties.height, Some(400));
+        assert_eq!(properties.floating, Some(true));
+
+        rules.remove(1);
+        assert_eq!(rules.properties_for(&window("foot", "")).height, Some(600));
+
+        // Reloading drops the script's rules
+        rules.reload();
+        assert_eq!(rules.properties_for(&window("foot", "")).floating, None);
+    }
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use smithay::{
+    desktop::{Kind, Window},
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+    wayland::{
+        compositor::with_states,
+        shell::xdg::{ToplevelState, XdgToplevelSurfaceRoleAttributes},
+    },
+};
+
+static NEXT_WINDOW_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Stable identifier handed to the runtime, as `Window` cannot cross into JS
+struct WindowId(u32);
+
+/// Set when a window asked for focus it was not given, until it gets focus
+struct Urgent(Cell<bool>);
+
+pub trait WindowExt {
+    fn id(&self) -> u32;
+    fn app_id(&self) -> Option<String>;
+    fn title(&self) -> Option<String>;
+    /// The state last acknowledged by the client
+    fn current_state(&self) -> ToplevelState;
+    fn server_decorated(&self) -> bool;
+    fn urgent(&self) -> bool;
+    fn set_urgent(&self, urgent: bool);
+}
+
+impl WindowExt for Window {
+    fn id(&self) -> u32 {
+        self.user_data()
+            .insert_if_missing(|| WindowId(NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed)));
+        self.user_data().get::<WindowId>().unwrap().0
+    }
+
+    fn app_id(&self) -> Option<String> {
+        match self.toplevel() {
+            Kind::Xdg(toplevel) => with_states(toplevel.wl_surface(), |states| {
+                states
+                    .data_map
+                    .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .app_id
+                    .clone()
+            }),
+        }
+    }
+
+    fn title(&self) -> Option<String> {
+        match self.toplevel() {
+            Kind::Xdg(toplevel) => with_states(toplevel.wl_surface(), |states| {
+                states
+                    .data_map
+                    .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .title
+                    .clone()
+            }),
+        }
+    }
+
+    fn current_state(&self) -> ToplevelState {
+        match self.toplevel() {
+            Kind::Xdg(toplevel) => with_states(toplevel.wl_surface(), |states| {
+                states
+                    .data_map
+                    .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .current
+                    .clone()
+            }),
+        }
+    }
+
+    fn server_decorated(&self) -> bool {
+        self.current_state().decoration_mode == Some(Mode::ServerSide)
+    }
+
+    fn urgent(&self) -> bool {
+        self.user_data()
+            .get::<Urgent>()
+            .map(|urgent| urgent.0.get())
+            .unwrap_or(false)
+    }
+
+    fn set_urgent(&self, urgent: bool) {
+        self.user_data()
+            .insert_if_missing(|| Urgent(Cell::new(false)));
+        self.user_data().get::<Urgent>().unwrap().0.set(urgent);
+    }
+}
@@ -5,7 +5,7 @@ use std::sync::Mutex;
 use smithay::{
     backend::renderer::utils::{on_commit_buffer_handler, with_renderer_surface_state},
     delegate_compositor,
-    desktop::{layer_map_for_output, Kind, LayerSurface, PopupKind, WindowSurfaceType},
+    desktop::{layer_map_for_output, Kind, LayerSurface, PopupKind, Window, WindowSurfaceType},
     reexports::wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle},
     wayland::{
         compositor::{with_states, CompositorHandler, CompositorState},
@@ -18,7 +18,7 @@ use smithay::{
     },
 };
 
-use super::{output::active_output, State};
+use super::{output::active_output, presentation::commit_feedback, State};
 
 impl CompositorHandler for State {
     fn compositor_state(&mut self) -> &mut CompositorState {
@@ -29,6 +29,8 @@ impl CompositorHandler for State {
     fn commit(&mut self, dh: &DisplayHandle, surface: &WlSurface) {
         // Load buffers
         on_commit_buffer_handler(surface);
+        commit_feedback(surface);
+        self.common.pointer_constraints_state.commit(surface);
 
         // Map Windows, Layers, Popups
         if let Some((window, seat)) = self
@@ -41,7 +43,7 @@ impl CompositorHandler for State {
         {
             match window.toplevel() {
                 Kind::Xdg(toplevel) => {
-                    if self.toplevel_ensure_initial_configure(&toplevel)
+                    if self.toplevel_ensure_initial_configure(dh, &window, &toplevel)
                         && with_renderer_surface_state(&surface, |state| {
                             state.wl_buffer().is_some()
                         })
@@ -92,7 +94,12 @@ impl CompositorHandler for State {
 }
 
 impl State {
-    fn toplevel_ensure_initial_configure(&mut self, toplevel: &ToplevelSurface) -> bool {
+    fn toplevel_ensure_initial_configure(
+        &mut self,
+        dh: &DisplayHandle,
+        window: &Window,
+        toplevel: &ToplevelSurface,
+    ) -> bool {
         let initial_configure_sent = with_states(toplevel.wl_surface(), |states| {
             states
                 .data_map
@@ -103,8 +110,11 @@ impl State {
                 .initial_configure_sent
         });
         if !initial_configure_sent {
-            toplevel.with_pending_state(|states| states.size = None);
-            toplevel.send_configure();
+            if self.common.shell.before_map_hook && !self.common.shell.fallback_active {
+                self.request_initial_layout(window);
+            } else {
+                self.configure_initial(dh, window, None);
+            }
         }
         initial_configure_sent
     }
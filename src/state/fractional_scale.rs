@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `wp_fractional_scale_v1`, which smithay does not implement yet
+
+use std::{cell::Cell, sync::Mutex};
+
+use smithay::{
+    desktop::{layer_map_for_output, Window},
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        protocol::wl_surface::WlSurface,
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
+    utils::IsAlive,
+    wayland::compositor::{with_states, with_surface_tree_downward, TraversalAction},
+};
+
+use crate::shell::{workspace::Workspace, Shell};
+
+use self::protocol::{
+    wp_fractional_scale_manager_v1::{self, WpFractionalScaleManagerV1},
+    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+};
+
+use super::State;
+
+#[allow(non_upper_case_globals, non_camel_case_types, clippy::all)]
+mod protocol {
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("resources/protocols/fractional-scale-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("resources/protocols/fractional-scale-v1.xml");
+}
+
+/// Scales are sent as the numerator of a fraction over 120
+const SCALE_DENOMINATOR: f64 = 120.0;
+
+pub struct FractionalScaleState {
+    _global: GlobalId,
+}
+
+impl FractionalScaleState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        FractionalScaleState {
+            _global: dh.create_global::<State, WpFractionalScaleManagerV1, _>(1, ()),
+        }
+    }
+}
+
+/// A surface's fractional scale object, with the last scale it was sent
+struct FractionalScale(Mutex<Option<(WpFractionalScaleV1, Option<u32>)>>);
+
+/// Tells every surface in the tree about a new preferred scale
+fn send_preferred_scale(surface: &WlSurface, scale: f64) {
+    let scale = (scale * SCALE_DENOMINATOR).round() as u32;
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |_, states, _| {
+            if let Some(fractional) = states.data_map.get::<FractionalScale>() {
+                if let Some((object, sent)) = fractional.0.lock().unwrap().as_mut() {
+                    if *sent != Some(scale) {
+                        object.preferred_scale(scale);
+                        *sent = Some(scale);
+                    }
+                }
+            }
+        },
+        |_, _, _| true,
+    );
+}
+
+/// The scale a window was last told to prefer, to notice when it enters an output of another
+struct WindowScale(Cell<Option<f64>>);
+
+/// The scale of the first output a window is on
+fn window_scale(workspace: &Workspace, window: &Window) -> f64 {
+    workspace
+        .space
+        .outputs_for_window(window)
+        .first()
+        .map(|output| output.current_scale().fractional_scale())
+        .unwrap_or(1.0)
+}
+
+/// Sends a window its preferred scale, unless it already has it or `force` is set
+fn update_window_scale(workspace: &Workspace, window: &Window, force: bool) {
+    let scale = window_scale(workspace, window);
+    let userdata = window.user_data();
+    userdata.insert_if_missing(|| WindowScale(Cell::new(None)));
+    let sent = userdata
+        .get::<WindowScale>()
+        .unwrap()
+        .0
+        .replace(Some(scale));
+    if force || sent != Some(scale) {
+        send_preferred_scale(window.toplevel().wl_surface(), scale);
+    }
+}
+
+impl Shell {
+    /// Sends each surface the scale of the output it is on, for when an output scale changes or
+    /// a surface gets a fractional scale object
+    pub fn update_preferred_scales(&self) {
+        for workspace in &self.workspaces {
+            for window in workspace.space.windows() {
+                update_window_scale(workspace, window, true);
+            }
+        }
+
+        for output in &self.outputs {
+            let scale = output.current_scale().fractional_scale();
+            for layer_surface in layer_map_for_output(output).layers() {
+                send_preferred_scale(layer_surface.wl_surface(), scale);
+            }
+        }
+    }
+
+    /// Sends windows that entered an output of another scale their new preferred scale
+    pub fn update_window_scales(&self) {
+        for workspace in &self.workspaces {
+            for window in workspace.space.windows() {
+                update_window_scale(workspace, window, false);
+            }
+        }
+    }
+}
+
+impl GlobalDispatch<WpFractionalScaleManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpFractionalScaleManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WpFractionalScaleManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        manager: &WpFractionalScaleManagerV1,
+        request: wp_fractional_scale_manager_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_fractional_scale_manager_v1::Request::GetFractionalScale { id, surface } => {
+                let exists = with_states(&surface, |states| {
+                    states
+                        .data_map
+                        .insert_if_missing_threadsafe(|| FractionalScale(Mutex::new(None)));
+                    states
+                        .data_map
+                        .get::<FractionalScale>()
+                        .unwrap()
+                        .0
+                        .lock()
+                        .unwrap()
+                        .is_some()
+                });
+                if exists {
+                    manager.post_error(
+                        dh,
+                        wp_fractional_scale_manager_v1::Error::FractionalScaleExists,
+                        "the surface already has a fractional scale object",
+                    );
+                    return;
+                }
+
+                let object = data_init.init(id, surface.clone());
+                with_states(&surface, |states| {
+                    *states
+                        .data_map
+                        .get::<FractionalScale>()
+                        .unwrap()
+                        .0
+                        .lock()
+                        .unwrap() = Some((object, None));
+                });
+                // Surfaces not on an output yet are sent their scale once they enter one
+                state.common.shell.update_preferred_scales();
+            }
+            wp_fractional_scale_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, WlSurface> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _object: &WpFractionalScaleV1,
+        request: wp_fractional_scale_v1::Request,
+        surface: &WlSurface,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_fractional_scale_v1::Request::Destroy => {
+                if surface.alive() {
+                    with_states(surface, |states| {
+                        if let Some(fractional) = states.data_map.get::<FractionalScale>() {
+                            *fractional.0.lock().unwrap() = None;
+                        }
+                    });
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(_state: &mut Self, _client: ClientId, _object: ObjectId, _surface: &WlSurface) {}
+}
@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Idle tracking, with `ext_idle_notifier_v1` to tell clients about it and
+//! `zwp_idle_inhibit_manager_v1` to let them prevent it
+
+use std::time::{Duration, Instant};
+
+use smithay::{
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            RegistrationToken,
+        },
+        wayland_protocols::wp::idle_inhibit::zv1::server::{
+            zwp_idle_inhibit_manager_v1::{self, ZwpIdleInhibitManagerV1},
+            zwp_idle_inhibitor_v1::{self, ZwpIdleInhibitorV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            protocol::wl_surface::WlSurface,
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    wayland::compositor::get_parent,
+};
+
+use crate::{runtime::messages::RuntimeMessage, shell::Shell};
+
+use self::protocol::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::{self, ExtIdleNotifierV1},
+};
+
+use super::State;
+
+#[allow(non_upper_case_globals, non_camel_case_types, clippy::all)]
+mod protocol {
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("resources/protocols/ext-idle-notify-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("resources/protocols/ext-idle-notify-v1.xml");
+}
+
+/// Counts down to idle from the last activity
+struct IdleTimer {
+    timeout: Duration,
+    since: Instant,
+    idle: bool,
+}
+
+impl IdleTimer {
+    fn new(timeout: Duration) -> Self {
+        IdleTimer {
+            timeout,
+            since: Instant::now(),
+            idle: false,
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        (!self.idle).then(|| self.since + self.timeout)
+    }
+
+    fn due(&self, now: Instant) -> bool {
+        self.deadline()
+            .map(|deadline| deadline <= now)
+            .unwrap_or(false)
+    }
+}
+
+struct IdleNotification {
+    object: ExtIdleNotificationV1,
+    timer: IdleTimer,
+}
+
+pub struct IdleState {
+    _notifier_global: GlobalId,
+    _inhibit_global: GlobalId,
+    /// The timeout the script set, after which it hears the session is idle
+    timeout: Option<IdleTimer>,
+    notifications: Vec<IdleNotification>,
+    inhibitors: Vec<(ZwpIdleInhibitorV1, WlSurface)>,
+    /// Fires at the earliest deadline, which only ever moves later on activity
+    timer: Option<RegistrationToken>,
+}
+
+impl IdleState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        IdleState {
+            _notifier_global: dh.create_global::<State, ExtIdleNotifierV1, _>(1, ()),
+            _inhibit_global: dh.create_global::<State, ZwpIdleInhibitManagerV1, _>(1, ()),
+            timeout: None,
+            notifications: Vec::new(),
+            inhibitors: Vec::new(),
+            timer: None,
+        }
+    }
+
+    fn timers_mut(&mut self) -> impl Iterator<Item = &mut IdleTimer> {
+        self.timeout.iter_mut().chain(
+            self.notifications
+                .iter_mut()
+                .map(|notification| &mut notification.timer),
+        )
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.timeout
+            .iter()
+            .chain(
+                self.notifications
+                    .iter()
+                    .map(|notification| &notification.timer),
+            )
+            .filter_map(IdleTimer::deadline)
+            .min()
+    }
+
+    /// Whether an inhibitor is on a surface that is currently shown
+    fn inhibited(&self, shell: &Shell) -> bool {
+        if self.inhibitors.is_empty() {
+            return false;
+        }
+        let visible = shell
+            .outputs()
+            .flat_map(|output| shell.visible_surfaces(output))
+            .collect::<Vec<_>>();
+        self.inhibitors.iter().any(|(_, surface)| {
+            let mut root = surface.clone();
+            while let Some(parent) = get_parent(&root) {
+                root = parent;
+            }
+            visible.contains(&root)
+        })
+    }
+}
+
+impl State {
+    /// Restarts every idle countdown, waking up whatever went idle
+    pub fn notify_activity(&mut self) {
+        let now = Instant::now();
+        let idle = &mut self.common.idle_state;
+        if let Some(timeout) = &mut idle.timeout {
+            if timeout.idle {
+                let _ = self
+                    .common
+                    .shell
+                    .active_workspace()
+                    .runtime_sender
+                    .send(RuntimeMessage::Idle { idle: false });
+            }
+        }
+        for notification in &idle.notifications {
+            if notification.timer.idle {
+                notification.object.resumed();
+            }
+        }
+        for timer in idle.timers_mut() {
+            timer.since = now;
+            timer.idle = false;
+        }
+
+        if idle.timer.is_none() {
+            self.schedule_idle_check();
+        }
+    }
+
+    /// Sets how long the session may go without activity before the script is told it is idle
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.common.idle_state.timeout = timeout.map(IdleTimer::new);
+        self.schedule_idle_check();
+    }
+
+    /// Replaces the pending check with one at the earliest deadline
+    fn schedule_idle_check(&mut self) {
+        let idle = &mut self.common.idle_state;
+        if let Some(token) = idle.timer.take() {
+            self.common.event_loop_handle.remove(token);
+        }
+        if let Some(deadline) = idle.next_deadline() {
+            let token = self
+                .common
+                .event_loop_handle
+                .insert_source(Timer::from_deadline(deadline), |_, _, data| {
+                    data.state.common.idle_state.timer = None;
+                    data.state.check_idle();
+                    TimeoutAction::Drop
+                })
+                .expect("Failed to initialise idle timer");
+            idle.timer = Some(token);
+        }
+    }
+
+    fn check_idle(&mut self) {
+        let now = Instant::now();
+        let idle = &mut self.common.idle_state;
+        if idle.inhibited(&self.common.shell) {
+            // Counting starts over once the inhibiting surface is out of sight
+            for timer in idle.timers_mut() {
+                timer.since = now;
+            }
+        } else {
+            if let Some(timeout) = &mut idle.timeout {
+                if timeout.due(now) {
+                    timeout.idle = true;
+                    let _ = self
+                        .common
+                        .shell
+                        .active_workspace()
+                        .runtime_sender
+                        .send(RuntimeMessage::Idle { idle: true });
+                }
+            }
+            for notification in &mut idle.notifications {
+                if notification.timer.due(now) {
+                    notification.timer.idle = true;
+                    notification.object.idled();
+                }
+            }
+        }
+        self.schedule_idle_check();
+    }
+}
+
+impl GlobalDispatch<ExtIdleNotifierV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtIdleNotifierV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _notifier: &ExtIdleNotifierV1,
+        request: ext_idle_notifier_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // There is only the one seat, so activity on any seat counts
+            ext_idle_notifier_v1::Request::GetIdleNotification { id, timeout, .. } => {
+                let object = data_init.init(id, ());
+                state
+                    .common
+                    .idle_state
+                    .notifications
+                    .push(IdleNotification {
+                        object,
+                        timer: IdleTimer::new(Duration::from_millis(timeout as u64)),
+                    });
+                state.schedule_idle_check();
+            }
+            ext_idle_notifier_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _notification: &ExtIdleNotificationV1,
+        request: ext_idle_notification_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            ext_idle_notification_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state
+            .common
+            .idle_state
+            .notifications
+            .retain(|notification| notification.object.id() != object);
+    }
+}
+
+impl GlobalDispatch<ZwpIdleInhibitManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpIdleInhibitManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ZwpIdleInhibitManagerV1,
+        request: zwp_idle_inhibit_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_idle_inhibit_manager_v1::Request::CreateInhibitor { id, surface } => {
+                let inhibitor = data_init.init(id, ());
+                state
+                    .common
+                    .idle_state
+                    .inhibitors
+                    .push((inhibitor, surface));
+            }
+            zwp_idle_inhibit_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitorV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _inhibitor: &ZwpIdleInhibitorV1,
+        request: zwp_idle_inhibitor_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_idle_inhibitor_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state
+            .common
+            .idle_state
+            .inhibitors
+            .retain(|(inhibitor, _)| inhibitor.id() != object);
+    }
+}
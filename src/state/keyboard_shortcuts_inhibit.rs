@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `zwp_keyboard_shortcuts_inhibit_manager_v1`, letting clients such as remote desktops and
+//! virtual machines receive the keys the compositor would otherwise claim
+
+use smithay::reexports::{
+    wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::{
+        zwp_keyboard_shortcuts_inhibit_manager_v1::{self, ZwpKeyboardShortcutsInhibitManagerV1},
+        zwp_keyboard_shortcuts_inhibitor_v1::{self, ZwpKeyboardShortcutsInhibitorV1},
+    },
+    wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        protocol::wl_surface::WlSurface,
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
+};
+
+use crate::{input::bindings::KeyCombo, runtime::messages::RuntimeMessage};
+
+use super::{CommonState, State};
+
+/// The combination that takes the keys back from an inhibiting client
+const DEFAULT_ESCAPE: &str = "Super+Escape";
+
+struct Inhibitor {
+    object: ZwpKeyboardShortcutsInhibitorV1,
+    surface: WlSurface,
+    active: bool,
+    /// The escape combination was pressed, the inhibitor stays inactive until its surface
+    /// loses and regains focus
+    escaped: bool,
+}
+
+pub struct KeyboardShortcutsInhibitState {
+    _global: GlobalId,
+    inhibitors: Vec<Inhibitor>,
+    escape: Option<KeyCombo>,
+    inhibited: bool,
+}
+
+impl KeyboardShortcutsInhibitState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        KeyboardShortcutsInhibitState {
+            _global: dh.create_global::<State, ZwpKeyboardShortcutsInhibitManagerV1, _>(1, ()),
+            inhibitors: Vec::new(),
+            escape: DEFAULT_ESCAPE.parse().ok(),
+            inhibited: false,
+        }
+    }
+
+    /// Whether the focused client receives every key instead of the bindings
+    pub fn is_inhibited(&self) -> bool {
+        self.inhibited
+    }
+
+    /// The combination still handled while inhibited, if any
+    pub fn escape(&self) -> Option<&KeyCombo> {
+        self.escape.as_ref()
+    }
+
+    pub fn set_escape(&mut self, escape: Option<KeyCombo>) {
+        self.escape = escape;
+    }
+
+    pub fn reset_escape(&mut self) {
+        self.escape = DEFAULT_ESCAPE.parse().ok();
+    }
+
+    /// Activates the inhibitor of the focused surface and deactivates the rest, returning whether
+    /// shortcuts went from inhibited to not or the other way around
+    fn update(&mut self, focus: Option<&WlSurface>) -> bool {
+        for inhibitor in &mut self.inhibitors {
+            let focused = focus == Some(&inhibitor.surface);
+            if !focused {
+                inhibitor.escaped = false;
+            }
+            let active = focused && !inhibitor.escaped;
+            if active != inhibitor.active {
+                inhibitor.active = active;
+                if active {
+                    inhibitor.object.active();
+                } else {
+                    inhibitor.object.inactive();
+                }
+            }
+        }
+
+        let inhibited = self.inhibitors.iter().any(|inhibitor| inhibitor.active);
+        let changed = inhibited != self.inhibited;
+        self.inhibited = inhibited;
+        changed
+    }
+}
+
+impl CommonState {
+    /// Brings the inhibitors in line with the keyboard focus, telling the script when shortcuts
+    /// become inhibited or available again
+    pub fn update_shortcuts_inhibit(&mut self) {
+        let focus = self
+            .last_active_seat
+            .get_keyboard()
+            .and_then(|keyboard| keyboard.current_focus());
+        let state = &mut self.keyboard_shortcuts_inhibit_state;
+        if state.update(focus.as_ref()) {
+            let _ = self.shell.active_workspace().runtime_sender.send(
+                RuntimeMessage::ShortcutsInhibited {
+                    inhibited: state.inhibited,
+                },
+            );
+        }
+    }
+
+    /// Gives the keys back to the compositor until the inhibiting surface is focused again
+    pub fn escape_shortcuts_inhibit(&mut self) {
+        for inhibitor in &mut self.keyboard_shortcuts_inhibit_state.inhibitors {
+            if inhibitor.active {
+                inhibitor.escaped = true;
+            }
+        }
+        self.update_shortcuts_inhibit();
+    }
+}
+
+impl GlobalDispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpKeyboardShortcutsInhibitManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        manager: &ZwpKeyboardShortcutsInhibitManagerV1,
+        request: zwp_keyboard_shortcuts_inhibit_manager_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // There is only the one seat, so a surface can only have a single inhibitor
+            zwp_keyboard_shortcuts_inhibit_manager_v1::Request::InhibitShortcuts {
+                id,
+                surface,
+                ..
+            } => {
+                let object = data_init.init(id, ());
+                let inhibitors = &mut state.common.keyboard_shortcuts_inhibit_state.inhibitors;
+                if inhibitors
+                    .iter()
+                    .any(|inhibitor| inhibitor.surface == surface)
+                {
+                    manager.post_error(
+                        dh,
+                        zwp_keyboard_shortcuts_inhibit_manager_v1::Error::AlreadyInhibited,
+                        "the surface already inhibits shortcuts",
+                    );
+                    return;
+                }
+
+                inhibitors.push(Inhibitor {
+                    object,
+                    surface,
+                    active: false,
+                    escaped: false,
+                });
+                state.common.update_shortcuts_inhibit();
+            }
+            zwp_keyboard_shortcuts_inhibit_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitorV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _inhibitor: &ZwpKeyboardShortcutsInhibitorV1,
+        request: zwp_keyboard_shortcuts_inhibitor_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_keyboard_shortcuts_inhibitor_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state
+            .common
+            .keyboard_shortcuts_inhibit_state
+            .inhibitors
+            .retain(|inhibitor| inhibitor.object.id() != object);
+        state.common.update_shortcuts_inhibit();
+    }
+}
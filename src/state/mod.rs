@@ -11,34 +11,58 @@ use smithay::{
             Display, DisplayHandle,
         },
     },
+    utils::{Logical, Point},
     wayland::{
         compositor::CompositorState,
         data_device::DataDeviceState,
         dmabuf::DmabufState,
-        output::OutputManagerState,
+        output::{Output, OutputManagerState},
         primary_selection::PrimarySelectionState,
         seat::{Seat, SeatState},
+        shell::xdg::decoration::XdgDecorationState,
         shm::ShmState,
         viewporter::ViewporterState,
+        xdg_activation::XdgActivationState,
         Serial,
     },
 };
 
+use self::{
+    fractional_scale::FractionalScaleState, idle::IdleState,
+    keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState, output::active_output,
+    pointer_constraints::PointerConstraintsState, presentation::PresentationState,
+    relative_pointer::RelativePointerState, session_lock::SessionLockState,
+    virtual_keyboard::VirtualKeyboardState, virtual_pointer::VirtualPointerState,
+};
 use crate::{
-    backend::winit::state::WinitState, input::add_seat, log::LogState,
-    runtime::messages::RuntimeMessage, shell::Shell,
+    backend::winit::state::WinitState,
+    input::{add_seat, bindings::Bindings},
+    log::LogState,
+    runtime::messages::RuntimeMessage,
+    shell::{decorations::DecorationPolicy, Shell},
 };
 
 mod buffer;
 mod compositor;
 mod data_device;
 mod dmabuf;
+mod fractional_scale;
+mod idle;
+mod keyboard_shortcuts_inhibit;
 mod layer_shell;
 pub(crate) mod output;
+mod pointer_constraints;
+mod presentation;
 mod primary_selection;
+mod relative_pointer;
 mod seat;
+mod session_lock;
 mod shm;
 mod viewporter;
+mod virtual_keyboard;
+mod virtual_pointer;
+mod xdg_activation;
+mod xdg_decoration;
 mod xdg_shell;
 
 pub enum BackendData {
@@ -81,20 +105,34 @@ pub struct CommonState {
     pub shell: Shell,
     pub seats: Vec<Seat<State>>,
     pub last_active_seat: Seat<State>,
+    pub bindings: Bindings,
 
     pub start_time: Instant,
     pub should_stop: bool,
     pub log: LogState,
+    /// When the compositor last had to point out a script error itself
+    pub script_error: Option<Instant>,
 
     // Wayland State
     pub compositor_state: CompositorState,
     pub data_device_state: DataDeviceState,
     pub dmabuf_state: DmabufState,
+    pub fractional_scale_state: FractionalScaleState,
+    pub idle_state: IdleState,
+    pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
     pub output_state: OutputManagerState,
+    pub pointer_constraints_state: PointerConstraintsState,
+    pub presentation_state: PresentationState,
     pub primary_selection_state: PrimarySelectionState,
+    pub relative_pointer_state: RelativePointerState,
     pub seat_state: SeatState<State>,
+    pub session_lock_state: SessionLockState,
     pub shm_state: ShmState,
     pub viewporter_state: ViewporterState,
+    pub virtual_keyboard_state: VirtualKeyboardState,
+    pub virtual_pointer_state: VirtualPointerState,
+    pub xdg_activation_state: XdgActivationState,
+    pub xdg_decoration_state: XdgDecorationState,
 }
 
 impl State {
@@ -119,22 +157,35 @@ impl State {
                 shell: Shell::new(&dh, runtime_sender),
                 seats: vec![initial_seat.clone()],
                 last_active_seat: initial_seat,
+                bindings: Bindings::default(),
 
                 start_time: Instant::now(),
                 should_stop: false,
                 log,
+                script_error: None,
 
                 compositor_state: CompositorState::new::<Self, _>(dh, slog_scope::logger()),
                 data_device_state: DataDeviceState::new::<Self, _>(dh, slog_scope::logger()),
                 dmabuf_state: DmabufState::new(),
+                fractional_scale_state: FractionalScaleState::new(dh),
+                idle_state: IdleState::new(dh),
+                keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState::new(dh),
                 primary_selection_state: PrimarySelectionState::new::<Self, _>(
                     dh,
                     slog_scope::logger(),
                 ),
+                relative_pointer_state: RelativePointerState::new(dh),
                 output_state: OutputManagerState::new_with_xdg_output::<Self>(dh),
+                pointer_constraints_state: PointerConstraintsState::new(dh),
+                presentation_state: PresentationState::new(dh),
                 seat_state: SeatState::<Self>::new(),
+                session_lock_state: SessionLockState::new(dh),
                 shm_state: ShmState::new::<Self, _>(dh, vec![], slog_scope::logger()),
                 viewporter_state: ViewporterState::new::<Self, _>(dh, slog_scope::logger()),
+                virtual_keyboard_state: VirtualKeyboardState::new(dh),
+                virtual_pointer_state: VirtualPointerState::new(dh),
+                xdg_activation_state: XdgActivationState::new::<Self, _>(dh, slog_scope::logger()),
+                xdg_decoration_state: XdgDecorationState::new::<Self, _>(dh, slog_scope::logger()),
             },
         }
     }
@@ -142,6 +193,20 @@ impl State {
     pub fn new_client_state(&self) -> ClientState {
         ClientState {}
     }
+
+    /// Drops everything a script configured, before a new script starts or the built-in window
+    /// manager takes over
+    pub fn reset_script_state(&mut self, dh: &DisplayHandle, fallback: bool) {
+        self.common.shell.fallback_active = fallback;
+        self.common.shell.before_map_hook = false;
+        self.common.shell.rules.reload();
+        self.set_decoration_policy(DecorationPolicy::default());
+        self.set_idle_timeout(None);
+        self.common.keyboard_shortcuts_inhibit_state.reset_escape();
+        self.flush_initial_layouts(dh);
+        self.stop_key_repeat();
+        self.common.bindings.clear();
+    }
 }
 
 impl CommonState {
@@ -153,8 +218,42 @@ impl CommonState {
         active_seat: &Seat<State>,
         serial: Option<Serial>,
     ) {
+        // Only lock surfaces may take focus while the session is locked
+        if self.session_lock_state.is_locked()
+            && surface
+                .map(|surface| !self.session_lock_state.is_lock_surface(surface))
+                .unwrap_or(false)
+        {
+            return;
+        }
         self.shell.set_focus(dh, surface, active_seat, serial);
         self.shell.update_active(self.seats.iter());
+        self.update_shortcuts_inhibit();
+    }
+
+    /// The surface under a point that may take pointer input
+    pub fn surface_under(
+        &self,
+        output: &Output,
+        point: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        if self.session_lock_state.is_locked() {
+            self.session_lock_state.surface_under(output, point)
+        } else {
+            self.shell.surface_under(output, point)
+        }
+    }
+
+    /// The surface receiving the pointer events of a seat, which during a grab stays the one the
+    /// grab started on
+    pub fn pointer_focus(&self, seat: &Seat<State>) -> Option<WlSurface> {
+        let pointer = seat.get_pointer()?;
+        match pointer.grab_start_data() {
+            Some(start_data) => start_data.focus.map(|(surface, _)| surface),
+            None => self
+                .surface_under(&active_output(seat, self), pointer.current_location())
+                .map(|(surface, _)| surface),
+        }
     }
 
     /// Deno Function
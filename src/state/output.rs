@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use smithay::{
     delegate_output,
@@ -12,10 +15,16 @@ use super::{CommonState, State};
 
 pub trait OutputExt {
     fn geometry(&self) -> Rectangle<i32, Logical>;
+    /// Asks for the script to be told when the next frame of this output is presented
+    fn request_frame(&self);
+    /// Whether the script asked for this frame, clearing the request
+    fn take_frame_request(&self) -> bool;
 }
 
 pub struct ActiveOutput(pub RefCell<Output>);
 
+struct FrameRequested(AtomicBool);
+
 impl OutputExt for Output {
     fn geometry(&self) -> Rectangle<i32, Logical> {
         Rectangle::from_loc_and_size(self.current_location(), {
@@ -30,6 +39,23 @@ impl OutputExt for Output {
                 .to_i32_round()
         })
     }
+
+    fn request_frame(&self) {
+        let userdata = self.user_data();
+        userdata.insert_if_missing(|| FrameRequested(AtomicBool::new(false)));
+        userdata
+            .get::<FrameRequested>()
+            .unwrap()
+            .0
+            .store(true, Ordering::Release);
+    }
+
+    fn take_frame_request(&self) -> bool {
+        self.user_data()
+            .get::<FrameRequested>()
+            .map(|requested| requested.0.swap(false, Ordering::AcqRel))
+            .unwrap_or(false)
+    }
 }
 
 pub fn active_output(seat: &Seat<State>, state: &CommonState) -> Output {
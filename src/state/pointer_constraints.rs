@@ -0,0 +1,527 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `zwp_pointer_constraints_v1`, letting clients lock the pointer in place or confine it to a
+//! region of their surface
+
+use smithay::{
+    desktop::{utils::under_from_surface_tree, WindowSurfaceType},
+    reexports::{
+        wayland_protocols::wp::pointer_constraints::zv1::server::{
+            zwp_confined_pointer_v1::{self, ZwpConfinedPointerV1},
+            zwp_locked_pointer_v1::{self, ZwpLockedPointerV1},
+            zwp_pointer_constraints_v1::{self, Lifetime, ZwpPointerConstraintsV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            protocol::{wl_region::WlRegion, wl_surface::WlSurface},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+        },
+    },
+    utils::{Logical, Point},
+    wayland::{
+        compositor::{get_region_attributes, RegionAttributes},
+        seat::MotionEvent,
+        SERIAL_COUNTER,
+    },
+};
+
+use super::State;
+
+enum ConstraintObject {
+    Locked(ZwpLockedPointerV1),
+    Confined(ZwpConfinedPointerV1),
+}
+
+/// Whether a constraint applies, which the protocol objects only get told about
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Lifecycle {
+    persistent: bool,
+    active: bool,
+    /// A oneshot constraint that was deactivated, waiting for the client to destroy it
+    defunct: bool,
+}
+
+impl Lifecycle {
+    fn activate(&mut self) {
+        self.active = true;
+    }
+
+    fn deactivate(&mut self) {
+        self.active = false;
+        self.defunct = !self.persistent;
+    }
+}
+
+/// Where the pointer goes once a lock goes away, as the client hinted
+pub struct Unlock {
+    surface: WlSurface,
+    origin: Point<i32, Logical>,
+    location: Point<f64, Logical>,
+}
+
+struct PointerConstraint {
+    object: ConstraintObject,
+    surface: WlSurface,
+    /// Surface-local, `None` being the whole surface
+    region: Option<RegionAttributes>,
+    pending_region: Option<Option<RegionAttributes>>,
+    /// Where a locked pointer should end up once unlocked, surface-local
+    hint: Option<Point<f64, Logical>>,
+    pending_hint: Option<Point<f64, Logical>>,
+    lifecycle: Lifecycle,
+    /// Where the surface was when the pointer was last over it
+    origin: Point<i32, Logical>,
+}
+
+/// Whether a surface-local point is within a constraint's region, `None` being the whole surface
+fn in_region(region: Option<&RegionAttributes>, point: Point<f64, Logical>) -> bool {
+    region
+        .map(|region| region.contains(point.to_i32_floor()))
+        .unwrap_or(true)
+}
+
+/// Where a pointer may go on its way from `current` to `target`, or `None` if it has to stay put.
+/// A locked pointer never moves, a confined one slides along the edge of its region.
+fn constrain_motion(
+    locked: bool,
+    current: Point<f64, Logical>,
+    target: Point<f64, Logical>,
+    contains: impl Fn(Point<f64, Logical>) -> bool,
+) -> Option<Point<f64, Logical>> {
+    if locked {
+        return None;
+    }
+    [
+        target,
+        (target.x, current.y).into(),
+        (current.x, target.y).into(),
+    ]
+    .into_iter()
+    .find(|point| contains(*point))
+}
+
+impl PointerConstraint {
+    fn id(&self) -> ObjectId {
+        match &self.object {
+            ConstraintObject::Locked(object) => object.id(),
+            ConstraintObject::Confined(object) => object.id(),
+        }
+    }
+
+    fn locked(&self) -> bool {
+        matches!(self.object, ConstraintObject::Locked(_))
+    }
+
+    /// Whether a point is within the surface and the constraint's region
+    fn contains(&self, point: Point<f64, Logical>) -> bool {
+        let on_surface = under_from_surface_tree(
+            &self.surface,
+            point,
+            self.origin,
+            WindowSurfaceType::TOPLEVEL,
+        )
+        .map(|(surface, _)| surface == self.surface)
+        .unwrap_or(false);
+        on_surface && in_region(self.region.as_ref(), point - self.origin.to_f64())
+    }
+
+    fn activate(&mut self) {
+        self.lifecycle.activate();
+        match &self.object {
+            ConstraintObject::Locked(object) => object.locked(),
+            ConstraintObject::Confined(object) => object.confined(),
+        }
+    }
+
+    /// Returns where to move the pointer if this lifts a lock with a position hint
+    fn deactivate(&mut self) -> Option<Unlock> {
+        let unlock = self.unlock();
+        self.lifecycle.deactivate();
+        match &self.object {
+            ConstraintObject::Locked(object) => object.unlocked(),
+            ConstraintObject::Confined(object) => object.unconfined(),
+        }
+        unlock
+    }
+
+    /// Where the pointer goes if the constraint stops applying now
+    fn unlock(&self) -> Option<Unlock> {
+        match self.hint {
+            Some(hint) if self.locked() && self.lifecycle.active => Some(Unlock {
+                surface: self.surface.clone(),
+                origin: self.origin,
+                location: self.origin.to_f64() + hint,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub struct PointerConstraintsState {
+    _global: GlobalId,
+    constraints: Vec<PointerConstraint>,
+}
+
+impl PointerConstraintsState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        PointerConstraintsState {
+            _global: dh.create_global::<State, ZwpPointerConstraintsV1, _>(1, ()),
+            constraints: Vec::new(),
+        }
+    }
+
+    /// Applies the region and cursor position hint the surface just committed
+    pub fn commit(&mut self, surface: &WlSurface) {
+        for constraint in self
+            .constraints
+            .iter_mut()
+            .filter(|constraint| constraint.surface == *surface)
+        {
+            if let Some(region) = constraint.pending_region.take() {
+                constraint.region = region;
+            }
+            if let Some(hint) = constraint.pending_hint.take() {
+                constraint.hint = Some(hint);
+            }
+        }
+    }
+
+    /// Activates the constraint of the surface under the pointer once the pointer is within its
+    /// region, and deactivates those of every other surface. Returns where to move the pointer
+    /// if that lifted a lock with a position hint.
+    pub fn update(
+        &mut self,
+        under: Option<&(WlSurface, Point<i32, Logical>)>,
+        location: Point<f64, Logical>,
+    ) -> Option<Unlock> {
+        let mut any_active = self
+            .constraints
+            .iter()
+            .any(|constraint| constraint.lifecycle.active);
+        let mut unlock = None;
+        for constraint in self
+            .constraints
+            .iter_mut()
+            .filter(|constraint| !constraint.lifecycle.defunct)
+        {
+            match under.filter(|(surface, _)| *surface == constraint.surface) {
+                Some((_, origin)) => {
+                    constraint.origin = *origin;
+                    if !constraint.lifecycle.active && !any_active && constraint.contains(location)
+                    {
+                        constraint.activate();
+                        any_active = true;
+                    }
+                }
+                None if constraint.lifecycle.active => {
+                    unlock = constraint.deactivate().or(unlock);
+                    any_active = false;
+                }
+                None => {}
+            }
+        }
+        unlock
+    }
+
+    /// Where the pointer may go on its way from `current` to `target`, or `None` if it has to
+    /// stay put
+    pub fn constrain(
+        &self,
+        current: Point<f64, Logical>,
+        target: Point<f64, Logical>,
+    ) -> Option<Point<f64, Logical>> {
+        match self
+            .constraints
+            .iter()
+            .find(|constraint| constraint.lifecycle.active)
+        {
+            Some(constraint) => constrain_motion(constraint.locked(), current, target, |point| {
+                constraint.contains(point)
+            }),
+            None => Some(target),
+        }
+    }
+}
+
+impl State {
+    /// Moves the pointer to the position the client hinted at, for when a lock goes away
+    pub fn warp_to_hint(&mut self, dh: &DisplayHandle, unlock: Unlock) {
+        let time = self.common.start_time.elapsed().as_millis() as u32;
+        let seat = self.common.last_active_seat.clone();
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.motion(
+                self,
+                dh,
+                &MotionEvent {
+                    location: unlock.location,
+                    focus: Some((unlock.surface, unlock.origin)),
+                    serial: SERIAL_COUNTER.next_serial(),
+                    time,
+                },
+            );
+        }
+    }
+
+    fn add_pointer_constraint(
+        &mut self,
+        dh: &DisplayHandle,
+        constraints: &ZwpPointerConstraintsV1,
+        object: ConstraintObject,
+        surface: WlSurface,
+        region: Option<WlRegion>,
+        lifetime: WEnum<Lifetime>,
+    ) {
+        let state = &mut self.common.pointer_constraints_state;
+        // There is only the one seat, so a surface can only have a single constraint
+        if state
+            .constraints
+            .iter()
+            .any(|constraint| constraint.surface == surface)
+        {
+            constraints.post_error(
+                dh,
+                zwp_pointer_constraints_v1::Error::AlreadyConstrained,
+                "the surface already has a pointer constraint",
+            );
+            return;
+        }
+
+        state.constraints.push(PointerConstraint {
+            object,
+            surface,
+            region: region.as_ref().map(get_region_attributes),
+            pending_region: None,
+            hint: None,
+            pending_hint: None,
+            lifecycle: Lifecycle {
+                persistent: lifetime == WEnum::Value(Lifetime::Persistent),
+                ..Default::default()
+            },
+            origin: Point::default(),
+        });
+    }
+
+    fn remove_pointer_constraint(&mut self, object: ObjectId) -> Option<PointerConstraint> {
+        let constraints = &mut self.common.pointer_constraints_state.constraints;
+        let pos = constraints
+            .iter()
+            .position(|constraint| constraint.id() == object)?;
+        Some(constraints.remove(pos))
+    }
+}
+
+impl GlobalDispatch<ZwpPointerConstraintsV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpPointerConstraintsV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpPointerConstraintsV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        constraints: &ZwpPointerConstraintsV1,
+        request: zwp_pointer_constraints_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_pointer_constraints_v1::Request::LockPointer {
+                id,
+                surface,
+                region,
+                lifetime,
+                ..
+            } => {
+                let object = ConstraintObject::Locked(data_init.init(id, ()));
+                state.add_pointer_constraint(dh, constraints, object, surface, region, lifetime);
+            }
+            zwp_pointer_constraints_v1::Request::ConfinePointer {
+                id,
+                surface,
+                region,
+                lifetime,
+                ..
+            } => {
+                let object = ConstraintObject::Confined(data_init.init(id, ()));
+                state.add_pointer_constraint(dh, constraints, object, surface, region, lifetime);
+            }
+            zwp_pointer_constraints_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpLockedPointerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        locked: &ZwpLockedPointerV1,
+        request: zwp_locked_pointer_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let constraints = &mut state.common.pointer_constraints_state.constraints;
+        let constraint = constraints
+            .iter_mut()
+            .find(|constraint| constraint.id() == locked.id());
+        match request {
+            zwp_locked_pointer_v1::Request::SetCursorPositionHint {
+                surface_x,
+                surface_y,
+            } => {
+                if let Some(constraint) = constraint {
+                    constraint.pending_hint = Some((surface_x, surface_y).into());
+                }
+            }
+            zwp_locked_pointer_v1::Request::SetRegion { region } => {
+                if let Some(constraint) = constraint {
+                    constraint.pending_region = Some(region.as_ref().map(get_region_attributes));
+                }
+            }
+            zwp_locked_pointer_v1::Request::Destroy => {
+                if let Some(unlock) = state
+                    .remove_pointer_constraint(locked.id())
+                    .and_then(|constraint| constraint.unlock())
+                {
+                    state.warp_to_hint(dh, unlock);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state.remove_pointer_constraint(object);
+    }
+}
+
+impl Dispatch<ZwpConfinedPointerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        confined: &ZwpConfinedPointerV1,
+        request: zwp_confined_pointer_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_confined_pointer_v1::Request::SetRegion { region } => {
+                if let Some(constraint) = state
+                    .common
+                    .pointer_constraints_state
+                    .constraints
+                    .iter_mut()
+                    .find(|constraint| constraint.id() == confined.id())
+                {
+                    constraint.pending_region = Some(region.as_ref().map(get_region_attributes));
+                }
+            }
+            zwp_confined_pointer_v1::Request::Destroy => {
+                state.remove_pointer_constraint(confined.id());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state.remove_pointer_constraint(object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{utils::Rectangle, wayland::compositor::RectangleKind};
+
+    use super::*;
+
+    /// A 100x100 region with the top left quarter cut out
+    fn region() -> RegionAttributes {
+        RegionAttributes {
+            rects: vec![
+                (
+                    RectangleKind::Add,
+                    Rectangle::from_loc_and_size((0, 0), (100, 100)),
+                ),
+                (
+                    RectangleKind::Subtract,
+                    Rectangle::from_loc_and_size((0, 0), (50, 50)),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn regions_limit_where_the_pointer_may_be() {
+        let region = region();
+        assert!(in_region(None, (-10.0, 5000.0).into()));
+        assert!(in_region(Some(&region), (75.0, 25.0).into()));
+        assert!(in_region(Some(&region), (50.0, 50.0).into()));
+        assert!(!in_region(Some(&region), (25.0, 25.0).into()));
+        assert!(!in_region(Some(&region), (99.5, 100.0).into()));
+    }
+
+    #[test]
+    fn locked_pointers_stay_put() {
+        let anywhere = |_| true;
+        assert_eq!(
+            constrain_motion(true, (10.0, 10.0).into(), (20.0, 20.0).into(), anywhere),
+            None
+        );
+    }
+
+    #[test]
+    fn confined_pointers_slide_along_the_edge() {
+        let region = region();
+        let contains = |point| in_region(Some(&region), point);
+
+        // Free to move within the region
+        assert_eq!(
+            constrain_motion(false, (60.0, 60.0).into(), (70.0, 80.0).into(), contains),
+            Some((70.0, 80.0).into())
+        );
+        // Moving diagonally into the cut out corner keeps the part along the edge
+        assert_eq!(
+            constrain_motion(false, (60.0, 40.0).into(), (40.0, 30.0).into(), contains),
+            Some((60.0, 30.0).into())
+        );
+        assert_eq!(
+            constrain_motion(false, (40.0, 60.0).into(), (30.0, 40.0).into(), contains),
+            Some((30.0, 60.0).into())
+        );
+        // Straight into it there is nowhere to go
+        assert_eq!(
+            constrain_motion(false, (60.0, 40.0).into(), (40.0, 40.0).into(), contains),
+            None
+        );
+    }
+
+    #[test]
+    fn oneshot_constraints_become_defunct() {
+        let mut oneshot = Lifecycle::default();
+        oneshot.activate();
+        assert!(oneshot.active);
+        oneshot.deactivate();
+        assert!(!oneshot.active);
+        assert!(oneshot.defunct);
+
+        let mut persistent = Lifecycle {
+            persistent: true,
+            ..Default::default()
+        };
+        persistent.activate();
+        persistent.deactivate();
+        assert!(!persistent.active);
+        assert!(!persistent.defunct);
+    }
+}
@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `wp_presentation`, telling clients when their content updates reached the screen
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use smithay::{
+    desktop::{layer_map_for_output, PopupManager},
+    reexports::{
+        wayland_protocols::wp::presentation_time::server::{
+            wp_presentation::{self, WpPresentation},
+            wp_presentation_feedback::{self, WpPresentationFeedback},
+        },
+        wayland_server::{
+            backend::GlobalId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch,
+            DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    wayland::{
+        compositor::{with_states, with_surface_tree_downward, TraversalAction},
+        output::Output,
+        shell::wlr_layer::Layer as WlrLayer,
+    },
+};
+
+use crate::shell::Shell;
+
+use super::State;
+
+pub struct PresentationState {
+    _global: GlobalId,
+}
+
+impl PresentationState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        PresentationState {
+            _global: dh.create_global::<State, WpPresentation, _>(1, ()),
+        }
+    }
+}
+
+/// Feedback asked for the content update the surface has not committed yet
+struct PendingFeedback(Mutex<Vec<WpPresentationFeedback>>);
+
+/// Feedback for the committed content update, waiting for it to be presented
+struct CommittedFeedback(Mutex<Vec<WpPresentationFeedback>>);
+
+/// Counts the frames presented on an output
+struct PresentationSequence(AtomicU64);
+
+/// Timestamps are sent in `CLOCK_MONOTONIC`, as announced on bind
+fn monotonic_time() -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+/// Moves feedback over to the content update being committed. Feedback for an update that was
+/// never presented is discarded, since it is superseded by this one.
+pub fn commit_feedback(surface: &WlSurface) {
+    with_states(surface, |states| {
+        let pending = match states.data_map.get::<PendingFeedback>() {
+            Some(pending) => std::mem::take(&mut *pending.0.lock().unwrap()),
+            None => return,
+        };
+        states
+            .data_map
+            .insert_if_missing_threadsafe(|| CommittedFeedback(Mutex::new(Vec::new())));
+        let superseded = std::mem::replace(
+            &mut *states
+                .data_map
+                .get::<CommittedFeedback>()
+                .unwrap()
+                .0
+                .lock()
+                .unwrap(),
+            pending,
+        );
+        for feedback in superseded {
+            feedback.discarded();
+        }
+    });
+}
+
+/// Takes the committed feedback of every surface in the tree
+fn take_feedback(surface: &WlSurface) -> Vec<WpPresentationFeedback> {
+    let mut feedback = Vec::new();
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |_, states, _| {
+            if let Some(committed) = states.data_map.get::<CommittedFeedback>() {
+                feedback.append(&mut committed.0.lock().unwrap());
+            }
+        },
+        |_, _, _| true,
+    );
+    feedback
+}
+
+/// A surface along with the popups opened on it
+fn with_popups(surface: WlSurface) -> impl Iterator<Item = WlSurface> {
+    let popups = PopupManager::popups_for_surface(&surface)
+        .into_iter()
+        .flatten()
+        .map(|(popup, _)| popup.wl_surface().clone())
+        .collect::<Vec<_>>();
+    std::iter::once(surface).chain(popups)
+}
+
+impl Shell {
+    /// The surfaces drawn on the output, matching what the renderer draws
+    pub(super) fn visible_surfaces(&self, output: &Output) -> Vec<WlSurface> {
+        let workspace = self.active_workspace();
+        let layer_map = layer_map_for_output(output);
+
+        let mut surfaces = Vec::new();
+        if let Some(window) = workspace.get_fullscreen(output) {
+            surfaces.push(window.toplevel().wl_surface().clone());
+            surfaces.extend(
+                layer_map
+                    .layers_on(WlrLayer::Overlay)
+                    .map(|layer_surface| layer_surface.wl_surface().clone()),
+            );
+        } else {
+            surfaces.extend(
+                workspace
+                    .space
+                    .windows()
+                    .filter(|window| workspace.space.outputs_for_window(window).contains(output))
+                    .map(|window| window.toplevel().wl_surface().clone()),
+            );
+            surfaces.extend(
+                layer_map
+                    .layers()
+                    .map(|layer_surface| layer_surface.wl_surface().clone()),
+            );
+        }
+        surfaces.into_iter().flat_map(with_popups).collect()
+    }
+
+    /// Sends presented feedback for every surface shown in the frame just submitted to the
+    /// output, and discarded feedback for the surfaces not shown on any output
+    pub fn send_presentation_feedback(
+        &self,
+        dh: &DisplayHandle,
+        output: &Output,
+        flags: wp_presentation_feedback::Kind,
+    ) {
+        let time = monotonic_time();
+        let refresh = output
+            .current_mode()
+            .filter(|mode| mode.refresh > 0)
+            .map(|mode| 1_000_000_000_000 / mode.refresh as u64)
+            .unwrap_or(0);
+        let userdata = output.user_data();
+        userdata.insert_if_missing(|| PresentationSequence(AtomicU64::new(0)));
+        let sequence = userdata
+            .get::<PresentationSequence>()
+            .unwrap()
+            .0
+            .fetch_add(1, Ordering::AcqRel);
+
+        let visible = self.visible_surfaces(output);
+        for feedback in visible.iter().flat_map(take_feedback) {
+            if let Ok(client) = dh.get_client(feedback.id()) {
+                for wl_output in output.client_outputs(&client) {
+                    feedback.sync_output(&wl_output);
+                }
+            }
+            feedback.presented(
+                (time.as_secs() >> 32) as u32,
+                time.as_secs() as u32,
+                time.subsec_nanos(),
+                refresh as u32,
+                (sequence >> 32) as u32,
+                sequence as u32,
+                flags,
+            );
+        }
+
+        let shown = self
+            .outputs()
+            .flat_map(|output| self.visible_surfaces(output))
+            .collect::<Vec<_>>();
+        let windows = self
+            .workspaces
+            .iter()
+            .flat_map(|workspace| workspace.space.windows())
+            .map(|window| window.toplevel().wl_surface().clone());
+        let layers = self
+            .outputs()
+            .flat_map(|output| {
+                layer_map_for_output(output)
+                    .layers()
+                    .map(|layer_surface| layer_surface.wl_surface().clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for surface in windows
+            .chain(layers)
+            .flat_map(with_popups)
+            .filter(|surface| !shown.contains(surface))
+        {
+            for feedback in take_feedback(&surface) {
+                feedback.discarded();
+            }
+        }
+    }
+}
+
+impl GlobalDispatch<WpPresentation, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpPresentation>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let presentation = data_init.init(resource, ());
+        presentation.clock_id(libc::CLOCK_MONOTONIC as u32);
+    }
+}
+
+impl Dispatch<WpPresentation, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _presentation: &WpPresentation,
+        request: wp_presentation::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_presentation::Request::Feedback { surface, callback } => {
+                let feedback = data_init.init(callback, ());
+                with_states(&surface, |states| {
+                    states
+                        .data_map
+                        .insert_if_missing_threadsafe(|| PendingFeedback(Mutex::new(Vec::new())));
+                    states
+                        .data_map
+                        .get::<PendingFeedback>()
+                        .unwrap()
+                        .0
+                        .lock()
+                        .unwrap()
+                        .push(feedback);
+                });
+            }
+            wp_presentation::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WpPresentationFeedback, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _feedback: &WpPresentationFeedback,
+        _request: wp_presentation_feedback::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The feedback object has no requests, it is destroyed by the event it gets
+    }
+}
@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `zwp_relative_pointer_manager_v1`, giving clients raw pointer motion
+
+use smithay::{
+    reexports::{
+        wayland_protocols::wp::relative_pointer::zv1::server::{
+            zwp_relative_pointer_manager_v1::{self, ZwpRelativePointerManagerV1},
+            zwp_relative_pointer_v1::{self, ZwpRelativePointerV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            protocol::wl_surface::WlSurface,
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    utils::{Logical, Point},
+};
+
+use super::State;
+
+pub struct RelativePointerState {
+    _global: GlobalId,
+    pointers: Vec<(ZwpRelativePointerV1, ClientId)>,
+}
+
+impl RelativePointerState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        RelativePointerState {
+            _global: dh.create_global::<State, ZwpRelativePointerManagerV1, _>(1, ()),
+            pointers: Vec::new(),
+        }
+    }
+
+    /// Sends motion to the relative pointers of the client the pointer is over, with the time in
+    /// microseconds
+    pub fn send_motion(
+        &self,
+        dh: &DisplayHandle,
+        focus: &WlSurface,
+        time: u64,
+        delta: Point<f64, Logical>,
+        delta_unaccel: Point<f64, Logical>,
+    ) {
+        let client = match dh.get_client(focus.id()) {
+            Ok(client) => client.id(),
+            Err(_) => return,
+        };
+        for (pointer, _) in self.pointers.iter().filter(|(_, c)| *c == client) {
+            pointer.relative_motion(
+                (time >> 32) as u32,
+                time as u32,
+                delta.x,
+                delta.y,
+                delta_unaccel.x,
+                delta_unaccel.y,
+            );
+        }
+    }
+}
+
+impl GlobalDispatch<ZwpRelativePointerManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpRelativePointerManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpRelativePointerManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _manager: &ZwpRelativePointerManagerV1,
+        request: zwp_relative_pointer_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // There is only the one seat, so every relative pointer follows its pointer
+            zwp_relative_pointer_manager_v1::Request::GetRelativePointer { id, .. } => {
+                let pointer = data_init.init(id, ());
+                state
+                    .common
+                    .relative_pointer_state
+                    .pointers
+                    .push((pointer, client.id()));
+            }
+            zwp_relative_pointer_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpRelativePointerV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _pointer: &ZwpRelativePointerV1,
+        request: zwp_relative_pointer_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_relative_pointer_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state
+            .common
+            .relative_pointer_state
+            .pointers
+            .retain(|(pointer, _)| pointer.id() != object);
+    }
+}
@@ -0,0 +1,358 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `ext_session_lock_manager_v1`, letting screen lockers such as swaylock lock the session
+
+use smithay::{
+    desktop::{
+        utils::{send_frames_surface_tree, under_from_surface_tree},
+        WindowSurfaceType,
+    },
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        protocol::wl_surface::WlSurface,
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
+    utils::{IsAlive, Logical, Point},
+    wayland::{compositor::give_role, output::Output, seat::MotionEvent, SERIAL_COUNTER},
+};
+
+use self::protocol::{
+    ext_session_lock_manager_v1::{self, ExtSessionLockManagerV1},
+    ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+    ext_session_lock_v1::{self, ExtSessionLockV1},
+};
+
+use super::{output::OutputExt, State};
+
+#[allow(non_upper_case_globals, non_camel_case_types, clippy::all)]
+mod protocol {
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("resources/protocols/ext-session-lock-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("resources/protocols/ext-session-lock-v1.xml");
+}
+
+const LOCK_SURFACE_ROLE: &str = "ext_session_lock_surface_v1";
+
+struct LockSurface {
+    object: ExtSessionLockSurfaceV1,
+    surface: WlSurface,
+    output: Output,
+}
+
+pub struct SessionLockState {
+    _global: GlobalId,
+    /// Stays set when the locker goes away without unlocking, so a crash never unlocks
+    locked: bool,
+    /// The lock holding the session, while its client is still around
+    locker: Option<ExtSessionLockV1>,
+    /// Whether the locker was told the session is locked
+    confirmed: bool,
+    /// Outputs that have not shown a locked frame yet
+    pending_outputs: Vec<Output>,
+    surfaces: Vec<LockSurface>,
+    /// Keyboard focus from before the session was locked, given back on unlock
+    previous_focus: Option<WlSurface>,
+}
+
+impl SessionLockState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        SessionLockState {
+            _global: dh.create_global::<State, ExtSessionLockManagerV1, _>(1, ()),
+            locked: false,
+            locker: None,
+            confirmed: false,
+            pending_outputs: Vec::new(),
+            surfaces: Vec::new(),
+            previous_focus: None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn is_lock_surface(&self, surface: &WlSurface) -> bool {
+        self.surfaces
+            .iter()
+            .any(|lock_surface| lock_surface.surface == *surface)
+    }
+
+    pub fn lock_surface(&self, output: &Output) -> Option<&WlSurface> {
+        self.surfaces
+            .iter()
+            .find(|lock_surface| lock_surface.output == *output)
+            .map(|lock_surface| &lock_surface.surface)
+    }
+
+    pub fn surface_under(
+        &self,
+        output: &Output,
+        point: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<i32, Logical>)> {
+        under_from_surface_tree(
+            self.lock_surface(output)?,
+            point,
+            output.current_location(),
+            WindowSurfaceType::ALL,
+        )
+    }
+
+    /// Sends frame callbacks to the lock surface of an output that just presented a frame, and
+    /// tells the locker once every output has been covered
+    pub fn frame_presented(&mut self, output: &Output, time: u32) {
+        if !self.locked {
+            return;
+        }
+        if let Some(surface) = self.lock_surface(output) {
+            send_frames_surface_tree(surface, time);
+        }
+
+        self.pending_outputs.retain(|pending| pending != output);
+        if self.pending_outputs.is_empty() && !self.confirmed {
+            if let Some(locker) = &self.locker {
+                locker.locked();
+                self.confirmed = true;
+            }
+        }
+    }
+}
+
+impl State {
+    fn lock_session(&mut self, dh: &DisplayHandle, lock: ExtSessionLockV1) {
+        let session_lock = &mut self.common.session_lock_state;
+        if session_lock.locker.is_some() {
+            lock.finished();
+            return;
+        }
+
+        // A locker restarted after a crash takes over the session that is still locked
+        if !session_lock.locked {
+            session_lock.locked = true;
+            session_lock.previous_focus = self
+                .common
+                .last_active_seat
+                .get_keyboard()
+                .and_then(|keyboard| keyboard.current_focus());
+            self.stop_key_repeat();
+            self.clear_input_focus(dh);
+        }
+
+        let session_lock = &mut self.common.session_lock_state;
+        session_lock.pending_outputs = self.common.shell.outputs().cloned().collect();
+        session_lock.confirmed = session_lock.pending_outputs.is_empty();
+        if session_lock.confirmed {
+            lock.locked();
+        }
+        session_lock.locker = Some(lock);
+    }
+
+    fn unlock_session(&mut self, dh: &DisplayHandle) {
+        let session_lock = &mut self.common.session_lock_state;
+        session_lock.locked = false;
+        session_lock.locker = None;
+        session_lock.confirmed = false;
+        session_lock.pending_outputs.clear();
+        session_lock.surfaces.clear();
+        let previous_focus = session_lock
+            .previous_focus
+            .take()
+            .filter(|surface| surface.alive());
+
+        self.clear_input_focus(dh);
+        let seat = self.common.last_active_seat.clone();
+        self.common
+            .set_focus(dh, previous_focus.as_ref(), &seat, None);
+    }
+
+    /// Takes focus away from whatever had it, as the surfaces allowed to have it changed
+    fn clear_input_focus(&mut self, dh: &DisplayHandle) {
+        let time = self.common.start_time.elapsed().as_millis() as u32;
+        for seat in self.common.seats.clone() {
+            self.common.set_focus(dh, None, &seat, None);
+            if let Some(pointer) = seat.get_pointer() {
+                pointer.motion(
+                    self,
+                    dh,
+                    &MotionEvent {
+                        location: pointer.current_location(),
+                        focus: None,
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl GlobalDispatch<ExtSessionLockManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtSessionLockManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtSessionLockManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ExtSessionLockManagerV1,
+        request: ext_session_lock_manager_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            ext_session_lock_manager_v1::Request::Lock { id } => {
+                let lock = data_init.init(id, ());
+                state.lock_session(dh, lock);
+            }
+            ext_session_lock_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        lock: &ExtSessionLockV1,
+        request: ext_session_lock_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let session_lock = &mut state.common.session_lock_state;
+        let is_locker = session_lock.locker.as_ref() == Some(lock);
+        match request {
+            ext_session_lock_v1::Request::GetLockSurface {
+                id,
+                surface,
+                output,
+            } => {
+                let object = data_init.init(id, ());
+                if give_role(&surface, LOCK_SURFACE_ROLE).is_err() {
+                    lock.post_error(
+                        dh,
+                        ext_session_lock_v1::Error::Role,
+                        "the surface already has a role",
+                    );
+                    return;
+                }
+                // Surfaces of a lock that was turned down are never shown
+                let output = match Output::from_resource(&output) {
+                    Some(output) if is_locker => output,
+                    _ => return,
+                };
+                if session_lock.lock_surface(&output).is_some() {
+                    lock.post_error(
+                        dh,
+                        ext_session_lock_v1::Error::DuplicateOutput,
+                        "the output already has a lock surface",
+                    );
+                    return;
+                }
+
+                let size = output.geometry().size;
+                object.configure(
+                    SERIAL_COUNTER.next_serial().into(),
+                    size.w as u32,
+                    size.h as u32,
+                );
+                session_lock.surfaces.push(LockSurface {
+                    object,
+                    surface: surface.clone(),
+                    output,
+                });
+
+                // The first lock surface gets the keyboard, clicks move it between them
+                let seat = state.common.last_active_seat.clone();
+                if seat
+                    .get_keyboard()
+                    .map(|keyboard| keyboard.current_focus().is_none())
+                    .unwrap_or(false)
+                {
+                    state.common.set_focus(dh, Some(&surface), &seat, None);
+                }
+            }
+            ext_session_lock_v1::Request::UnlockAndDestroy => {
+                if is_locker && session_lock.confirmed {
+                    state.unlock_session(dh);
+                } else {
+                    lock.post_error(
+                        dh,
+                        ext_session_lock_v1::Error::InvalidUnlock,
+                        "the session was never locked by this lock",
+                    );
+                }
+            }
+            ext_session_lock_v1::Request::Destroy => {
+                if is_locker && session_lock.confirmed {
+                    lock.post_error(
+                        dh,
+                        ext_session_lock_v1::Error::InvalidDestroy,
+                        "the session is locked, unlock_and_destroy must be used",
+                    );
+                } else if is_locker {
+                    // The locker gave up before the session was covered
+                    state.unlock_session(dh);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        let session_lock = &mut state.common.session_lock_state;
+        if session_lock
+            .locker
+            .as_ref()
+            .map(|locker| locker.id() == object)
+            .unwrap_or(false)
+        {
+            slog_scope::warn!("The screen locker went away, the session stays locked");
+            session_lock.locker = None;
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockSurfaceV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _lock_surface: &ExtSessionLockSurfaceV1,
+        request: ext_session_lock_surface_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // Lock surfaces are always drawn at the output size, whatever was acked
+            ext_session_lock_surface_v1::Request::AckConfigure { .. } => {}
+            ext_session_lock_surface_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state
+            .common
+            .session_lock_state
+            .surfaces
+            .retain(|lock_surface| lock_surface.object.id() != object);
+    }
+}
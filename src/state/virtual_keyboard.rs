@@ -0,0 +1,348 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `zwp_virtual_keyboard_manager_v1`, letting tools such as `wtype` type into clients
+//!
+//! Virtual keyboards bring their own keymap while the seat has a single one. The seat keyboard
+//! takes on the keymap of the virtual keyboard typing into it, so its keycodes and modifiers are
+//! passed on unchanged, and gets its own keymap back once a real keyboard is used again.
+
+use std::{fs::File, os::unix::prelude::*, ptr};
+
+use smithay::{
+    backend::input::KeyState,
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
+};
+use xkbcommon::xkb;
+
+use self::protocol::{
+    zwp_virtual_keyboard_manager_v1::{self, ZwpVirtualKeyboardManagerV1},
+    zwp_virtual_keyboard_v1::{self, ZwpVirtualKeyboardV1},
+};
+
+use crate::input::SeatXkbConfig;
+
+use super::State;
+
+#[allow(non_upper_case_globals, non_camel_case_types, clippy::all)]
+mod protocol {
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!(
+            "resources/protocols/virtual-keyboard-unstable-v1.xml"
+        );
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("resources/protocols/virtual-keyboard-unstable-v1.xml");
+}
+
+struct VirtualKeyboard {
+    object: ZwpVirtualKeyboardV1,
+    /// The keymap as the client shared it, once it compiled
+    keymap: Option<String>,
+    /// Keys held down
+    pressed: Vec<u32>,
+}
+
+pub struct VirtualKeyboardState {
+    _global: GlobalId,
+    keyboards: Vec<VirtualKeyboard>,
+    /// The virtual keyboard whose keymap the seat keyboard has, if any
+    active: Option<ObjectId>,
+}
+
+impl VirtualKeyboardState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        VirtualKeyboardState {
+            _global: dh.create_global::<State, ZwpVirtualKeyboardManagerV1, _>(1, ()),
+            keyboards: Vec::new(),
+            active: None,
+        }
+    }
+
+    fn keyboard_mut(&mut self, object: &ZwpVirtualKeyboardV1) -> Option<&mut VirtualKeyboard> {
+        self.keyboards
+            .iter_mut()
+            .find(|keyboard| keyboard.object == *object)
+    }
+}
+
+/// Reads the keymap a client shared
+fn read_keymap(fd: RawFd, size: u32) -> Option<String> {
+    let file = unsafe { File::from_raw_fd(fd) };
+    let size = size as usize;
+    let map = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            size,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if map == libc::MAP_FAILED {
+        return None;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(map as *const u8, size) };
+    let keymap = std::str::from_utf8(bytes)
+        .ok()
+        .map(|keymap| keymap.trim_end_matches('\0').to_string());
+    unsafe { libc::munmap(map, size) };
+    keymap
+}
+
+impl State {
+    /// Gives the seat keyboard the keymap of a virtual keyboard about to type into it
+    fn activate_virtual_keyboard(&mut self, dh: &DisplayHandle, object: &ZwpVirtualKeyboardV1) {
+        let keymap = self
+            .common
+            .virtual_keyboard_state
+            .keyboard_mut(object)
+            .and_then(|keyboard| keyboard.keymap.clone());
+        self.use_virtual_keymap(dh, object.id(), keymap);
+    }
+
+    fn use_virtual_keymap(&mut self, dh: &DisplayHandle, id: ObjectId, keymap: Option<String>) {
+        let virtual_keyboards = &mut self.common.virtual_keyboard_state;
+        if virtual_keyboards.active.as_ref() == Some(&id) {
+            return;
+        }
+        let keymap = match keymap {
+            Some(keymap) => keymap,
+            None => return,
+        };
+        virtual_keyboards.active = Some(id);
+
+        if let Some(keyboard) = self.common.last_active_seat.get_keyboard() {
+            if let Err(err) = keyboard.set_keymap_from_string(dh, keymap) {
+                slog_scope::warn!("Failed to use the keymap of a virtual keyboard: {}", err);
+            }
+        }
+    }
+
+    /// Gives the seat keyboard its own keymap back, after a virtual keyboard has typed into it
+    pub fn restore_seat_keymap(&mut self, dh: &DisplayHandle) {
+        if self.common.virtual_keyboard_state.active.take().is_none() {
+            return;
+        }
+        let seat = &self.common.last_active_seat;
+        let config = seat
+            .user_data()
+            .get::<SeatXkbConfig>()
+            .cloned()
+            .unwrap_or_default();
+        if let Some(keyboard) = seat.get_keyboard() {
+            if let Err(err) = keyboard.set_xkb_config(dh, config.xkb_config()) {
+                slog_scope::error!("Failed to restore the seat keymap: {}", err);
+            }
+        }
+    }
+
+    fn virtual_keyboard_key(
+        &mut self,
+        dh: &DisplayHandle,
+        object: &ZwpVirtualKeyboardV1,
+        time: u32,
+        keycode: u32,
+        state: KeyState,
+    ) {
+        let keyboard = match self.common.virtual_keyboard_state.keyboard_mut(object) {
+            Some(keyboard) => keyboard,
+            None => return,
+        };
+        match state {
+            KeyState::Pressed => keyboard.pressed.push(keycode),
+            KeyState::Released => match keyboard.pressed.iter().position(|code| *code == keycode) {
+                Some(pos) => {
+                    keyboard.pressed.remove(pos);
+                }
+                None => return,
+            },
+        }
+
+        self.activate_virtual_keyboard(dh, object);
+        self.keyboard_key(dh, keycode, state, time);
+    }
+
+    fn virtual_keyboard_modifiers(
+        &mut self,
+        dh: &DisplayHandle,
+        object: &ZwpVirtualKeyboardV1,
+        depressed: u32,
+        latched: u32,
+        locked: u32,
+        group: u32,
+    ) {
+        self.activate_virtual_keyboard(dh, object);
+        if let Some(keyboard) = self.common.last_active_seat.get_keyboard() {
+            keyboard.set_modifier_state(dh, depressed, latched, locked, group);
+        }
+    }
+
+    /// Releases whatever a virtual keyboard still held, so nothing stays stuck once it is gone.
+    /// The keys are released with its keymap, whichever keyboard typed last.
+    fn release_virtual_keyboard(&mut self, dh: &DisplayHandle, keyboard: VirtualKeyboard) {
+        let id = keyboard.object.id();
+        if !keyboard.pressed.is_empty() {
+            self.use_virtual_keymap(dh, id.clone(), keyboard.keymap);
+            let time = self.common.start_time.elapsed().as_millis() as u32;
+            for keycode in keyboard.pressed {
+                self.keyboard_key(dh, keycode, KeyState::Released, time);
+            }
+        }
+        if self.common.virtual_keyboard_state.active.as_ref() == Some(&id) {
+            self.restore_seat_keymap(dh);
+        }
+    }
+}
+
+impl GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpVirtualKeyboardManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpVirtualKeyboardManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ZwpVirtualKeyboardManagerV1,
+        request: zwp_virtual_keyboard_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // There is only the one seat, so every virtual keyboard types into its keyboard
+            zwp_virtual_keyboard_manager_v1::Request::CreateVirtualKeyboard { id, .. } => {
+                let object = data_init.init(id, ());
+                state
+                    .common
+                    .virtual_keyboard_state
+                    .keyboards
+                    .push(VirtualKeyboard {
+                        object,
+                        keymap: None,
+                        pressed: Vec::new(),
+                    });
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpVirtualKeyboardV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        object: &ZwpVirtualKeyboardV1,
+        request: zwp_virtual_keyboard_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let has_keymap = state
+            .common
+            .virtual_keyboard_state
+            .keyboard_mut(object)
+            .map(|keyboard| keyboard.keymap.is_some())
+            .unwrap_or(false);
+        match request {
+            zwp_virtual_keyboard_v1::Request::Keymap { format, fd, size } => {
+                // Compiled here only to check it, the seat keyboard compiles it again once used
+                let keymap = read_keymap(fd, size)
+                    .filter(|_| format == xkb::KEYMAP_FORMAT_TEXT_V1)
+                    .filter(|keymap| {
+                        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+                        xkb::Keymap::new_from_string(
+                            &context,
+                            keymap.clone(),
+                            xkb::KEYMAP_FORMAT_TEXT_V1,
+                            xkb::KEYMAP_COMPILE_NO_FLAGS,
+                        )
+                        .is_some()
+                    });
+                if keymap.is_none() {
+                    slog_scope::warn!("Failed to load the keymap of a virtual keyboard");
+                }
+                let virtual_keyboards = &mut state.common.virtual_keyboard_state;
+                if let Some(keyboard) = virtual_keyboards.keyboard_mut(object) {
+                    keyboard.keymap = keymap;
+                }
+                // A new keymap for the active keyboard is switched to with its next key
+                if virtual_keyboards.active.as_ref() == Some(&object.id()) {
+                    state.restore_seat_keymap(dh);
+                }
+            }
+            zwp_virtual_keyboard_v1::Request::Key { .. }
+            | zwp_virtual_keyboard_v1::Request::Modifiers { .. }
+                if !has_keymap =>
+            {
+                object.post_error(
+                    dh,
+                    zwp_virtual_keyboard_v1::Error::NoKeymap,
+                    "no keymap was set",
+                );
+            }
+            zwp_virtual_keyboard_v1::Request::Key {
+                time,
+                key,
+                state: key_state,
+            } => {
+                // Synthetic input counts as activity just like input from real devices
+                state.notify_activity();
+                let key_state = match key_state {
+                    0 => KeyState::Released,
+                    _ => KeyState::Pressed,
+                };
+                state.virtual_keyboard_key(dh, object, time, key, key_state);
+            }
+            zwp_virtual_keyboard_v1::Request::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+            } => {
+                state.notify_activity();
+                state.virtual_keyboard_modifiers(
+                    dh,
+                    object,
+                    mods_depressed,
+                    mods_latched,
+                    mods_locked,
+                    group,
+                );
+            }
+            zwp_virtual_keyboard_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        let keyboards = &mut state.common.virtual_keyboard_state.keyboards;
+        if let Some(pos) = keyboards
+            .iter()
+            .position(|keyboard| keyboard.object.id() == object)
+        {
+            let keyboard = keyboards.remove(pos);
+            state.common.event_loop_handle.insert_idle(move |data| {
+                let dh = data.display.handle();
+                data.state.release_virtual_keyboard(&dh, keyboard);
+            });
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `zwlr_virtual_pointer_manager_v1`, letting tools such as `ydotool` drive the pointer
+
+use smithay::{
+    backend::input::{Axis, AxisSource, ButtonState},
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        protocol::wl_pointer,
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+    },
+    utils::{Logical, Point, Rectangle},
+    wayland::output::Output,
+};
+
+use self::protocol::{
+    zwlr_virtual_pointer_manager_v1::{self, ZwlrVirtualPointerManagerV1},
+    zwlr_virtual_pointer_v1::{self, ZwlrVirtualPointerV1},
+};
+
+use super::{output::OutputExt, State};
+use crate::input::ScrollEvent;
+
+#[allow(non_upper_case_globals, non_camel_case_types, clippy::all)]
+mod protocol {
+    use smithay::reexports::wayland_server;
+    use smithay::reexports::wayland_server::protocol::*;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!(
+            "resources/protocols/wlr-virtual-pointer-unstable-v1.xml"
+        );
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!(
+        "resources/protocols/wlr-virtual-pointer-unstable-v1.xml"
+    );
+}
+
+struct VirtualPointer {
+    object: ZwlrVirtualPointerV1,
+    /// The output absolute motion is mapped to, instead of every output
+    output: Option<Output>,
+    /// Scrolling collected until the client ends the frame
+    scroll: Option<ScrollEvent>,
+}
+
+pub struct VirtualPointerState {
+    _global: GlobalId,
+    pointers: Vec<VirtualPointer>,
+}
+
+impl VirtualPointerState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        VirtualPointerState {
+            _global: dh.create_global::<State, ZwlrVirtualPointerManagerV1, _>(2, ()),
+            pointers: Vec::new(),
+        }
+    }
+
+    fn pointer_mut(&mut self, object: &ZwlrVirtualPointerV1) -> Option<&mut VirtualPointer> {
+        self.pointers
+            .iter_mut()
+            .find(|pointer| pointer.object == *object)
+    }
+}
+
+fn axis(axis: WEnum<wl_pointer::Axis>) -> Option<Axis> {
+    match axis {
+        WEnum::Value(wl_pointer::Axis::HorizontalScroll) => Some(Axis::Horizontal),
+        WEnum::Value(wl_pointer::Axis::VerticalScroll) => Some(Axis::Vertical),
+        _ => None,
+    }
+}
+
+fn axis_source(source: WEnum<wl_pointer::AxisSource>) -> Option<AxisSource> {
+    match source {
+        WEnum::Value(wl_pointer::AxisSource::Wheel) => Some(AxisSource::Wheel),
+        WEnum::Value(wl_pointer::AxisSource::Finger) => Some(AxisSource::Finger),
+        WEnum::Value(wl_pointer::AxisSource::Continuous) => Some(AxisSource::Continuous),
+        WEnum::Value(wl_pointer::AxisSource::WheelTilt) => Some(AxisSource::WheelTilt),
+        _ => None,
+    }
+}
+
+impl State {
+    /// Moves the pointer to a point given as a fraction of the output, or of all outputs
+    fn virtual_motion_absolute(
+        &mut self,
+        dh: &DisplayHandle,
+        output: Option<&Output>,
+        position: Point<f64, Logical>,
+        time: u32,
+    ) {
+        let geometry = match output {
+            Some(output) => Some(output.geometry()),
+            None => self
+                .common
+                .shell
+                .outputs()
+                .map(|output| output.geometry())
+                .reduce(Rectangle::merge),
+        };
+        if let Some(geometry) = geometry {
+            let location = geometry.loc.to_f64()
+                + Point::from((
+                    position.x * geometry.size.w as f64,
+                    position.y * geometry.size.h as f64,
+                ));
+            let seat = self.common.last_active_seat.clone();
+            self.pointer_motion(dh, &seat, location, time);
+        }
+    }
+
+    /// Adds to the scroll of the frame in progress, `None` stopping the axis
+    fn virtual_scroll(
+        &mut self,
+        dh: &DisplayHandle,
+        object: &ZwlrVirtualPointerV1,
+        time: u32,
+        axis_arg: WEnum<wl_pointer::Axis>,
+        scroll: Option<(f64, Option<f64>)>,
+    ) {
+        let axis = match axis(axis_arg) {
+            Some(axis) => axis,
+            None => {
+                object.post_error(
+                    dh,
+                    zwlr_virtual_pointer_v1::Error::InvalidAxis,
+                    "invalid axis",
+                );
+                return;
+            }
+        };
+        if let Some(pointer) = self.common.virtual_pointer_state.pointer_mut(object) {
+            let event = pointer
+                .scroll
+                .get_or_insert_with(|| ScrollEvent::new(AxisSource::Wheel, time));
+            let index = ScrollEvent::index(axis);
+            event.time = time;
+            event.amount[index] = scroll.map(|(amount, _)| amount);
+            event.discrete[index] = scroll.and_then(|(_, discrete)| discrete);
+            event.stop[index] = scroll.is_none();
+        }
+    }
+}
+
+impl GlobalDispatch<ZwlrVirtualPointerManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrVirtualPointerManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ZwlrVirtualPointerManagerV1,
+        request: zwlr_virtual_pointer_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // There is only the one seat, so every virtual pointer moves its pointer
+        let (id, output) = match request {
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointer { id, .. } => (id, None),
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointerWithOutput {
+                id,
+                output,
+                ..
+            } => (id, output.as_ref().and_then(Output::from_resource)),
+            zwlr_virtual_pointer_manager_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+        let object = data_init.init(id, ());
+        state
+            .common
+            .virtual_pointer_state
+            .pointers
+            .push(VirtualPointer {
+                object,
+                output,
+                scroll: None,
+            });
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        object: &ZwlrVirtualPointerV1,
+        request: zwlr_virtual_pointer_v1::Request,
+        _data: &(),
+        dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Synthetic input counts as activity just like input from real devices
+        if !matches!(request, zwlr_virtual_pointer_v1::Request::Destroy) {
+            state.notify_activity();
+        }
+
+        match request {
+            zwlr_virtual_pointer_v1::Request::Motion { time, dx, dy } => {
+                let delta = Point::from((dx, dy));
+                state.pointer_motion_relative(dh, delta, delta, time);
+            }
+            zwlr_virtual_pointer_v1::Request::MotionAbsolute {
+                time,
+                x,
+                y,
+                x_extent,
+                y_extent,
+            } => {
+                if x_extent == 0 || y_extent == 0 {
+                    return;
+                }
+                let output = state
+                    .common
+                    .virtual_pointer_state
+                    .pointer_mut(object)
+                    .and_then(|pointer| pointer.output.clone());
+                let position =
+                    Point::from((x as f64 / x_extent as f64, y as f64 / y_extent as f64));
+                state.virtual_motion_absolute(dh, output.as_ref(), position, time);
+            }
+            zwlr_virtual_pointer_v1::Request::Button {
+                time,
+                button,
+                state: button_state,
+            } => {
+                let button_state = match button_state {
+                    WEnum::Value(wl_pointer::ButtonState::Pressed) => ButtonState::Pressed,
+                    _ => ButtonState::Released,
+                };
+                state.pointer_button(dh, button, button_state, time);
+            }
+            zwlr_virtual_pointer_v1::Request::Axis { time, axis, value } => {
+                state.virtual_scroll(dh, object, time, axis, Some((value, None)));
+            }
+            zwlr_virtual_pointer_v1::Request::AxisDiscrete {
+                time,
+                axis,
+                value,
+                discrete,
+            } => {
+                state.virtual_scroll(dh, object, time, axis, Some((value, Some(discrete as f64))));
+            }
+            zwlr_virtual_pointer_v1::Request::AxisStop { time, axis } => {
+                state.virtual_scroll(dh, object, time, axis, None);
+            }
+            zwlr_virtual_pointer_v1::Request::AxisSource {
+                axis_source: source,
+            } => {
+                let source = match axis_source(source) {
+                    Some(source) => source,
+                    None => {
+                        object.post_error(
+                            dh,
+                            zwlr_virtual_pointer_v1::Error::InvalidAxisSource,
+                            "invalid axis source",
+                        );
+                        return;
+                    }
+                };
+                if let Some(pointer) = state.common.virtual_pointer_state.pointer_mut(object) {
+                    pointer
+                        .scroll
+                        .get_or_insert_with(|| ScrollEvent::new(source, 0))
+                        .source = source;
+                }
+            }
+            zwlr_virtual_pointer_v1::Request::Frame => {
+                let scroll = state
+                    .common
+                    .virtual_pointer_state
+                    .pointer_mut(object)
+                    .and_then(|pointer| pointer.scroll.take())
+                    .filter(|scroll| !scroll.is_empty());
+                if let Some(scroll) = scroll {
+                    state.pointer_axis(dh, scroll);
+                }
+            }
+            zwlr_virtual_pointer_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, object: ObjectId, _data: &()) {
+        state
+            .common
+            .virtual_pointer_state
+            .pointers
+            .retain(|pointer| pointer.object.id() != object);
+    }
+}
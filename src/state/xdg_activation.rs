@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::time::Duration;
+
+use smithay::{
+    delegate_xdg_activation,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle, Resource},
+    wayland::{
+        seat::Seat,
+        xdg_activation::{
+            XdgActivationHandler, XdgActivationState, XdgActivationToken, XdgActivationTokenData,
+        },
+    },
+};
+
+use crate::{input::RecentInput, runtime::messages::RuntimeMessage, shell::window::WindowExt};
+
+use super::State;
+
+/// How long a token stays good for after the client got it
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl XdgActivationHandler for State {
+    fn activation_state(&mut self) -> &mut XdgActivationState {
+        &mut self.common.xdg_activation_state
+    }
+
+    fn request_activation(
+        &mut self,
+        dh: &DisplayHandle,
+        _token: XdgActivationToken,
+        token_data: XdgActivationTokenData,
+        surface: WlSurface,
+    ) {
+        let window = match self.common.shell.window_for_surface(&surface) {
+            Some(window) => window,
+            None => return,
+        };
+
+        match activation_seat(dh, &token_data) {
+            Some(seat) => {
+                if let Some(workspace) = self.common.shell.space_for_window_mut(&surface) {
+                    workspace.space.raise_window(&window, true);
+                }
+                self.common
+                    .set_focus(dh, Some(window.toplevel().wl_surface()), &seat, None);
+            }
+            None => {
+                slog_scope::debug!("Refused to activate window {}", window.id());
+                window.set_urgent(true);
+                self.common
+                    .shell
+                    .active_workspace()
+                    .runtime_sender
+                    .send(RuntimeMessage::WindowUrgent { window })
+                    .unwrap();
+            }
+        }
+    }
+
+    fn destroy_activation(
+        &mut self,
+        _dh: &DisplayHandle,
+        _token: XdgActivationToken,
+        _token_data: XdgActivationTokenData,
+        _surface: WlSurface,
+    ) {
+    }
+}
+
+/// The seat a token may move focus on. Tokens have to be recent and made by the client that
+/// received the press whose serial they carry, so only what the user just interacted with
+/// can hand out focus.
+fn activation_seat(dh: &DisplayHandle, token_data: &XdgActivationTokenData) -> Option<Seat<State>> {
+    if token_data.timestamp.elapsed() > TOKEN_TIMEOUT {
+        return None;
+    }
+    let (serial, wl_seat) = token_data.serial.as_ref()?;
+    let seat = Seat::<State>::from_resource(wl_seat)?;
+    let client = dh.get_client(token_data.surface.as_ref()?.id()).ok()?;
+
+    let recent = seat.user_data().get::<RecentInput>()?;
+    if recent.went_to(*serial, &client.id()) {
+        Some(seat)
+    } else {
+        None
+    }
+}
+
+delegate_xdg_activation!(State);
@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    delegate_xdg_decoration,
+    reexports::{
+        wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+        wayland_server::DisplayHandle,
+    },
+    wayland::shell::xdg::{decoration::XdgDecorationHandler, ToplevelSurface},
+};
+
+use super::State;
+
+impl XdgDecorationHandler for State {
+    fn new_decoration(&mut self, _dh: &DisplayHandle, toplevel: ToplevelSurface) {
+        self.update_decoration_mode(&toplevel, None);
+    }
+
+    fn request_mode(&mut self, _dh: &DisplayHandle, toplevel: ToplevelSurface, mode: Mode) {
+        self.update_decoration_mode(&toplevel, Some(mode.into()));
+    }
+
+    fn unset_mode(&mut self, _dh: &DisplayHandle, toplevel: ToplevelSurface) {
+        self.update_decoration_mode(&toplevel, None);
+    }
+}
+
+delegate_xdg_decoration!(State);